@@ -0,0 +1,35 @@
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Where AWS data comes from, selected with `--backend`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum Backend {
+    /// The real AWS APIs, through the SDK.
+    #[default]
+    Sdk,
+    /// Canned data read from JSON files in a directory, for offline use.
+    Fixtures(PathBuf),
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s.eq_ignore_ascii_case("sdk") || s.eq_ignore_ascii_case("aws") => Ok(Backend::Sdk),
+            Some((kind, dir)) if kind.eq_ignore_ascii_case("fixtures") && !dir.is_empty() =>
+                Ok(Backend::Fixtures(PathBuf::from(dir))),
+            _ => Err(format!("'{}' isn't a backend; use 'sdk' or 'fixtures:DIR'", s)),
+        }
+    }
+}
+
+impl Display for Backend {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Backend::Sdk => write!(f, "sdk"),
+            Backend::Fixtures(dir) => write!(f, "fixtures:{}", dir.display()),
+        }
+    }
+}
@@ -0,0 +1,9 @@
+pub mod tr_aws_backend;
+pub mod e_backend;
+pub mod t_service_endpoint;
+pub mod t_sdk_backend;
+pub mod t_fixture_set;
+pub mod t_fixture_backend;
+pub mod t_recording_backend;
+#[cfg(test)]
+pub mod t_fixture_builder;
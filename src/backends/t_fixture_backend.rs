@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use aws_sdk_ec2::types::{Filter, Instance, ReservedInstances};
+use aws_sdk_iam::types::InstanceProfile;
+use aws_sdk_sts::operation::get_caller_identity::GetCallerIdentityOutput;
+
+use crate::backends::t_fixture_set::{call_key, in_region, FixtureSet, InstanceFixture};
+use crate::backends::tr_aws_backend::{Ec2Api, IamApi, PricingApi, StsApi, EC2_DESCRIBE_INSTANCES,
+                                      EC2_DESCRIBE_INSTANCE_TYPES, EC2_DESCRIBE_REGIONS, EC2_DESCRIBE_RESERVED_INSTANCES,
+                                      IAM_LIST_ACCOUNT_ALIASES, IAM_LIST_ATTACHED_ROLE_POLICIES, IAM_LIST_INSTANCE_PROFILES,
+                                      PRICING_GET_PRODUCTS, STS_GET_CALLER_IDENTITY, instance_action_operation};
+use crate::e_instance_action::InstanceAction;
+use crate::errors::jaws_error::{JawsError, JawsErrorKind};
+use crate::t_ec2_instance::InstanceSpec;
+use crate::Options;
+
+/// A backend which serves canned data from a `FixtureSet` and never touches the network.
+/// Instances and reservations are filtered by region, and instances by the filters given, as
+/// AWS would (though wildcards here ignore case).  Calls which failed when the data was
+/// recorded fail in the same way.  Instance actions take effect at once, and last as long as
+/// the backend: the fixtures themselves never change.
+pub struct FixtureBackend {
+    fixtures: FixtureSet,
+    region: String,
+    /// Instance states changed by actions, by instance ID.
+    states: Mutex<HashMap<String, String>>,
+}
+
+impl FixtureBackend {
+    /// Load the fixtures in `dir`.  Returns the backend and the region it works in: the one
+    /// given with `--region`, or otherwise the one in the identity fixture.
+    pub fn from_dir(dir: &Path, options: &Options) -> Result<(Self, String), JawsError> {
+        Self::from_fixtures(FixtureSet::load_dir(dir)?, options)
+    }
+
+    /// Serve `fixtures`, or with a role to assume in another account, the fixtures of that
+    /// account.
+    pub fn from_fixtures(mut fixtures: FixtureSet, options: &Options) -> Result<(Self, String), JawsError> {
+        if let Some(role_arn) = &options.assume_role {
+            let account = role_arn.split(':').nth(4).unwrap_or_default();
+            let own_account = fixtures.identity.as_ref().is_some_and(|i| i.account == account);
+            if !own_account {
+                fixtures = fixtures.accounts.remove(account).ok_or_else(|| JawsError::with_kind(JawsErrorKind::Credentials,
+                    format!("Couldn't assume {}: the fixtures have no account {}", role_arn, account)))?;
+            }
+        }
+
+        let region = options.region.clone()
+            .or_else(|| fixtures.identity.as_ref().and_then(|i| i.region.clone()))
+            .ok_or_else(|| JawsError::with_kind(JawsErrorKind::Config,
+                "The fixtures don't give a region.\n\nUse --region, or set \"region\" in identity.json.".to_string()))?;
+
+        Ok((Self { fixtures, region: region.clone(), states: Mutex::new(HashMap::new()) }, region))
+    }
+}
+
+#[async_trait]
+impl StsApi for FixtureBackend {
+    async fn get_caller_identity(&self) -> Result<GetCallerIdentityOutput, JawsError> {
+        self.fixtures.failure(&call_key(STS_GET_CALLER_IDENTITY, &[]))?;
+        self.fixtures.identity.as_ref()
+            .map(|identity| identity.to_sdk())
+            .ok_or_else(|| JawsError::with_kind(JawsErrorKind::Credentials,
+                "The fixtures have no caller identity.".to_string()))
+    }
+}
+
+#[async_trait]
+impl Ec2Api for FixtureBackend {
+    async fn describe_instances(&self, filters: &[Filter]) -> Result<Vec<Instance>, JawsError> {
+        self.fixtures.failure(&call_key(EC2_DESCRIBE_INSTANCES, &[&self.region]))?;
+        let states = self.states.lock().unwrap();
+        Ok(self.fixtures.instances.iter()
+            .filter(|i| in_region(None, i.availability_zone.as_deref(), &self.region))
+            .map(|i| match states.get(&i.instance_id) {
+                Some(state) => InstanceFixture { state: state.clone(), ..i.clone() },
+                None => i.clone(),
+            })
+            .filter(|i| filters.iter().all(|filter| i.matches(filter)))
+            .map(|i| i.to_sdk())
+            .collect())
+    }
+
+    async fn describe_active_reserved_instances(&self) -> Result<Vec<ReservedInstances>, JawsError> {
+        self.fixtures.failure(&call_key(EC2_DESCRIBE_RESERVED_INSTANCES, &[&self.region]))?;
+        Ok(self.fixtures.reserved_instances.iter()
+            .filter(|r| in_region(r.region.as_deref(), r.availability_zone.as_deref(), &self.region))
+            .map(|r| r.to_sdk())
+            .collect())
+    }
+
+    async fn describe_instance_types(&self) -> Result<HashMap<String, InstanceSpec>, JawsError> {
+        self.fixtures.failure(&call_key(EC2_DESCRIBE_INSTANCE_TYPES, &[&self.region]))?;
+        Ok(self.fixtures.instance_types.iter()
+            .map(|(name, spec)| (name.clone(), *spec))
+            .collect())
+    }
+
+    async fn describe_regions(&self) -> Result<Vec<String>, JawsError> {
+        self.fixtures.failure(&call_key(EC2_DESCRIBE_REGIONS, &[]))?;
+        Ok(self.fixtures.enabled_regions())
+    }
+
+    async fn change_instance_state(&self, action: InstanceAction, instance_ids: &[String], dry_run: bool)
+                                   -> Result<(), JawsError> {
+        self.fixtures.failure(&call_key(instance_action_operation(action), &[&self.region]))?;
+        for id in instance_ids {
+            if !self.fixtures.instances.iter()
+                .any(|i| &i.instance_id == id && in_region(None, i.availability_zone.as_deref(), &self.region)) {
+                return Err(JawsError::with_kind(JawsErrorKind::NotFound,
+                    format!("The instance ID '{}' does not exist", id)));
+            }
+        }
+
+        if let (false, Some(state)) = (dry_run, action.target_state()) {
+            let mut states = self.states.lock().unwrap();
+            for id in instance_ids {
+                states.insert(id.clone(), state.to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl IamApi for FixtureBackend {
+    async fn list_instance_profiles(&self) -> Result<Vec<InstanceProfile>, JawsError> {
+        self.fixtures.failure(&call_key(IAM_LIST_INSTANCE_PROFILES, &[]))?;
+        self.fixtures.instance_profiles.iter()
+            .map(|ip| ip.to_sdk())
+            .collect()
+    }
+
+    async fn list_attached_role_policies(&self, role_name: &str) -> Result<Vec<String>, JawsError> {
+        self.fixtures.failure(&call_key(IAM_LIST_ATTACHED_ROLE_POLICIES, &[role_name]))?;
+        self.fixtures.role_policies.get(role_name)
+            .cloned()
+            .ok_or_else(|| JawsError::with_kind(JawsErrorKind::NotFound,
+                format!("Couldn't list the policies of role {}: the fixtures don't have it", role_name)))
+    }
+
+    async fn get_account_alias(&self) -> Result<Option<String>, JawsError> {
+        self.fixtures.failure(&call_key(IAM_LIST_ACCOUNT_ALIASES, &[]))?;
+        Ok(self.fixtures.identity.as_ref().and_then(|i| i.account_alias.clone()))
+    }
+}
+
+#[async_trait]
+impl PricingApi for FixtureBackend {
+    async fn get_odm_rate(&self, region: &str, instance_type: &str) -> Result<f32, JawsError> {
+        self.fixtures.failure(&call_key(PRICING_GET_PRODUCTS, &[region, instance_type]))?;
+        self.fixtures.prices.get(region)
+            .and_then(|prices| prices.get(instance_type))
+            .copied()
+            .ok_or_else(|| JawsError::with_kind(JawsErrorKind::NotFound,
+                format!("The fixtures have no on-demand price for {} in {}", instance_type, region)))
+    }
+}
@@ -0,0 +1,108 @@
+use chrono::{TimeDelta, Utc};
+use clap::Parser;
+
+use crate::backends::t_fixture_set::{FixtureSet, IdentityFixture, InstanceFixture, InstanceProfileFixture,
+                                     RecurringChargeFixture, ReservationFixture};
+use crate::t_aws_handler::AWSHandler;
+use crate::t_ec2_instance::InstanceSpec;
+use crate::Options;
+
+/// The account and region of the fixtures built by `FixtureBuilder`.
+pub const ACCOUNT: &str = "111122223333";
+pub const REGION: &str = "eu-west-1";
+
+/// Builds canned AWS data for tests, for one account in eu-west-1, and a handler serving it.
+pub struct FixtureBuilder {
+    fixtures: FixtureSet,
+}
+
+impl FixtureBuilder {
+    pub fn new() -> Self {
+        Self {
+            fixtures: FixtureSet {
+                identity: Some(IdentityFixture {
+                    account: ACCOUNT.to_string(),
+                    arn: format!("arn:aws:iam::{}:user/test", ACCOUNT),
+                    region: Some(REGION.to_string()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn instance(mut self, instance: InstanceFixture) -> Self {
+        self.fixtures.instances.push(instance);
+        self
+    }
+
+    pub fn reservation(mut self, reservation: ReservationFixture) -> Self {
+        self.fixtures.reserved_instances.push(reservation);
+        self
+    }
+
+    /// The hourly on-demand price of an instance type.
+    pub fn price(mut self, instance_type: &str, hourly: f32) -> Self {
+        self.fixtures.prices.entry(REGION.to_string()).or_default().insert(instance_type.to_string(), hourly);
+        self
+    }
+
+    pub fn spec(mut self, instance_type: &str, vcpus: i32, memory_gib: i64) -> Self {
+        self.fixtures.instance_types.insert(instance_type.to_string(), InstanceSpec { vcpus, memory_gib });
+        self
+    }
+
+    /// An instance profile named `name`, whose one role has these managed policies.
+    pub fn instance_profile(mut self, name: &str, policies: &[&str]) -> Self {
+        let role = format!("{}-role", name);
+        self.fixtures.instance_profiles.push(InstanceProfileFixture {
+            arn: instance_profile_arn(name),
+            name: name.to_string(),
+            roles: vec![role.clone()],
+        });
+        self.fixtures.role_policies.insert(role, policies.iter().map(|p| p.to_string()).collect());
+        self
+    }
+
+    pub fn build(self) -> FixtureSet {
+        self.fixtures
+    }
+
+    /// Parse a command line, e.g. `["jaws", "res"]`, and get a handler for it which serves the
+    /// fixtures.
+    pub fn handler(self, args: &[&str]) -> (Options, AWSHandler) {
+        let options = Options::parse_from(args);
+        let handler = AWSHandler::from_fixtures(self.fixtures, &options).unwrap();
+        (options, handler)
+    }
+}
+
+/// An instance in eu-west-1a.
+pub fn instance(id: &str, instance_type: &str, state: &str) -> InstanceFixture {
+    InstanceFixture {
+        instance_id: id.to_string(),
+        state: state.to_string(),
+        instance_type: Some(instance_type.to_string()),
+        availability_zone: Some(format!("{}a", REGION)),
+        ..Default::default()
+    }
+}
+
+/// A one-year, no-upfront regional reservation, with 100 days to go.
+pub fn reservation(id: &str, instance_type: &str, count: i32, hourly: f64) -> ReservationFixture {
+    ReservationFixture {
+        reserved_instances_id: id.to_string(),
+        instance_type: instance_type.to_string(),
+        instance_count: count,
+        region: Some(REGION.to_string()),
+        end: Utc::now() + TimeDelta::days(100),
+        duration_seconds: 365 * 24 * 60 * 60,
+        offering_type: "No Upfront".to_string(),
+        recurring_charges: vec![RecurringChargeFixture { frequency: "Hourly".to_string(), amount: hourly }],
+        ..Default::default()
+    }
+}
+
+pub fn instance_profile_arn(name: &str) -> String {
+    format!("arn:aws:iam::{}:instance-profile/{}", ACCOUNT, name)
+}
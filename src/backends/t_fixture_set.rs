@@ -0,0 +1,524 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+use aws_sdk_ec2::primitives::DateTime as AwsDateTime;
+use aws_sdk_ec2::types::{Filter, IamInstanceProfile, Instance, InstanceState, InstanceStateName, InstanceType,
+                         OfferingTypeValues, Placement, RecurringCharge, RecurringChargeFrequency,
+                         ReservedInstances, Tag};
+use aws_sdk_iam::types::{InstanceProfile, Role};
+use aws_sdk_sts::operation::get_caller_identity::GetCallerIdentityOutput;
+use aws_smithy_types_convert::date_time::DateTimeExt;
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::errors::jaws_error::{JawsError, JawsErrorKind};
+use crate::matrix_handlers::t_matrix_filter::glob_match;
+use crate::t_ec2_instance::InstanceSpec;
+
+/// Canned AWS data, holding just the parts of each response JAWS uses.  In a fixtures
+/// directory each field is kept in its own file, named after the field (e.g. `instances.json`);
+/// missing files are treated as empty.  A recording (`--record`) keeps them all in one file.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FixtureSet {
+    pub identity: Option<IdentityFixture>,
+    pub instances: Vec<InstanceFixture>,
+    pub reserved_instances: Vec<ReservationFixture>,
+    /// Instance type specifications, by type name.
+    pub instance_types: BTreeMap<String, InstanceSpec>,
+    pub instance_profiles: Vec<InstanceProfileFixture>,
+    /// The names of the managed policies attached to each role, by role name.
+    pub role_policies: BTreeMap<String, Vec<String>>,
+    /// Linux on-demand hourly rates in USD, by region and then instance type.
+    pub prices: BTreeMap<String, BTreeMap<String, f32>>,
+    /// Calls which failed, by call key (see `call_key`), so that a recording reproduces them.
+    pub failures: BTreeMap<String, FailureFixture>,
+    /// The regions enabled for the account.  If there are none, they're the regions of the
+    /// identity, instances and reservations.
+    pub regions: Vec<String>,
+    /// The data of other accounts, reached by assuming a role (`--role-arn`), by account ID.
+    /// In a fixtures directory, each is a directory under `accounts`.
+    pub accounts: BTreeMap<String, FixtureSet>,
+}
+
+/// The caller identity.  `region` is the region used when `--region` isn't given.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct IdentityFixture {
+    pub account: String,
+    pub arn: String,
+    pub user_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account_alias: Option<String>,
+}
+
+/// An EC2 instance.  Its region is taken from its availability zone.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InstanceFixture {
+    pub instance_id: String,
+    pub state: String,
+    pub instance_type: Option<String>,
+    pub availability_zone: Option<String>,
+    pub public_ip: Option<String>,
+    pub private_ip: Option<String>,
+    pub spot_instance_request_id: Option<String>,
+    pub instance_profile_arn: Option<String>,
+    pub vpc_id: Option<String>,
+    pub tags: BTreeMap<String, String>,
+}
+
+/// An active EC2 reservation.  Zonal reservations take their region from their availability
+/// zone; regional ones need `region`.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReservationFixture {
+    pub reserved_instances_id: String,
+    pub instance_type: String,
+    pub instance_count: i32,
+    pub region: Option<String>,
+    pub availability_zone: Option<String>,
+    pub end: DateTime<Utc>,
+    pub duration_seconds: i64,
+    pub offering_type: String,
+    pub fixed_price: f32,
+    pub recurring_charges: Vec<RecurringChargeFixture>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct RecurringChargeFixture {
+    pub frequency: String,
+    pub amount: f64,
+}
+
+/// A call which failed, and how.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailureFixture {
+    pub kind: JawsErrorKind,
+    pub message: String,
+}
+
+/// An IAM instance profile and the names of its roles.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InstanceProfileFixture {
+    pub arn: String,
+    pub name: String,
+    pub roles: Vec<String>,
+}
+
+impl FixtureSet {
+    /// Load a fixtures directory.
+    pub fn load_dir(dir: &Path) -> Result<Self, JawsError> {
+        if !dir.is_dir() {
+            return Err(JawsError::with_kind(JawsErrorKind::Config,
+                format!("The fixtures directory '{}' doesn't exist.", dir.display())));
+        }
+
+        Ok(Self {
+            identity: read_fixture(dir, "identity")?,
+            instances: read_fixture(dir, "instances")?.unwrap_or_default(),
+            reserved_instances: read_fixture(dir, "reserved_instances")?.unwrap_or_default(),
+            instance_types: read_fixture(dir, "instance_types")?.unwrap_or_default(),
+            instance_profiles: read_fixture(dir, "instance_profiles")?.unwrap_or_default(),
+            role_policies: read_fixture(dir, "role_policies")?.unwrap_or_default(),
+            prices: read_fixture(dir, "prices")?.unwrap_or_default(),
+            failures: read_fixture(dir, "failures")?.unwrap_or_default(),
+            regions: read_fixture(dir, "regions")?.unwrap_or_default(),
+            accounts: read_account_dirs(dir)?,
+        })
+    }
+
+    /// Load a recording.
+    pub fn load_file(path: &Path) -> Result<Self, JawsError> {
+        let json = fs::read_to_string(path).map_err(|e| JawsError::with_kind(JawsErrorKind::Config,
+            format!("Couldn't read the recording '{}': {}", path.display(), e)).with_source(e))?;
+        serde_json::from_str(&json).map_err(|e| JawsError::with_kind(JawsErrorKind::Config,
+            format!("The recording '{}' isn't valid: {}", path.display(), e)).with_source(e))
+    }
+
+    /// Save as a recording.
+    pub fn save_file(&self, path: &Path) -> Result<(), JawsError> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| JawsError::new(format!("Couldn't serialize the recording: {}", e)).with_source(e))?;
+        fs::write(path, json).map_err(|e| JawsError::with_kind(JawsErrorKind::Output,
+            format!("Couldn't write the recording '{}': {}", path.display(), e)).with_source(e))
+    }
+
+    /// The regions enabled for the account, in order.
+    pub fn enabled_regions(&self) -> Vec<String> {
+        if !self.regions.is_empty() {
+            return self.regions.clone();
+        }
+
+        let zones = self.instances.iter().filter_map(|i| i.availability_zone.as_deref())
+            .chain(self.reserved_instances.iter().filter_map(|r| r.availability_zone.as_deref()));
+        let mut regions: Vec<String> = zones
+            .map(|az| az.trim_end_matches(|c: char| c.is_ascii_lowercase()).to_string())
+            .chain(self.reserved_instances.iter().filter_map(|r| r.region.clone()))
+            .chain(self.identity.iter().filter_map(|i| i.region.clone()))
+            .collect();
+        regions.sort();
+        regions.dedup();
+        regions
+    }
+
+    /// The recorded failure of a call, if it failed.
+    pub fn failure(&self, key: &str) -> Result<(), JawsError> {
+        match self.failures.get(key) {
+            Some(failure) => Err(JawsError::with_kind(failure.kind, failure.message.clone())),
+            None => Ok(()),
+        }
+    }
+
+    /// A copy with every AWS account ID replaced by a made-up one.  Each real ID always maps
+    /// to the same fake, so that references between accounts are kept.  Only the fields which
+    /// hold account IDs, and the account part of ARNs, are changed.
+    pub fn scrub_account_ids(&self) -> Self {
+        let mut accounts: Vec<String> = Vec::new();
+        self.account_ids(&mut accounts);
+
+        let fakes: HashMap<String, String> = accounts.into_iter()
+            .filter(|account| !account.is_empty())
+            .enumerate()
+            .map(|(n, account)| (account, format!("{:012}", n + 1)))
+            .collect();
+        self.scrubbed(&fakes)
+    }
+
+    /// Add the account IDs this set refers to, in order of first appearance, to `accounts`.
+    fn account_ids(&self, accounts: &mut Vec<String>) {
+        let arns = self.identity.iter().map(|i| i.arn.as_str())
+            .chain(self.instances.iter().filter_map(|i| i.instance_profile_arn.as_deref()))
+            .chain(self.instance_profiles.iter().map(|p| p.arn.as_str()))
+            .chain(self.failures.values().map(|f| f.message.as_str()));
+        let found = self.identity.iter().map(|i| i.account.clone())
+            .chain(self.accounts.keys().cloned())
+            .chain(arns.flat_map(arn_account_ids));
+
+        for account in found {
+            if !accounts.contains(&account) {
+                accounts.push(account);
+            }
+        }
+        for fixtures in self.accounts.values() {
+            fixtures.account_ids(accounts);
+        }
+    }
+
+    fn scrubbed(&self, fakes: &HashMap<String, String>) -> Self {
+        let fake = |account: &str| fakes.get(account).cloned().unwrap_or_else(|| account.to_string());
+
+        let mut scrubbed = self.clone();
+        if let Some(identity) = &mut scrubbed.identity {
+            identity.account = fake(&identity.account);
+            identity.arn = scrub_arns(&identity.arn, fakes);
+        }
+        for instance in &mut scrubbed.instances {
+            instance.instance_profile_arn = instance.instance_profile_arn.as_deref().map(|arn| scrub_arns(arn, fakes));
+        }
+        for profile in &mut scrubbed.instance_profiles {
+            profile.arn = scrub_arns(&profile.arn, fakes);
+        }
+        for failure in scrubbed.failures.values_mut() {
+            failure.message = scrub_arns(&failure.message, fakes);
+        }
+        scrubbed.accounts = self.accounts.iter()
+            .map(|(account, fixtures)| (fake(account), fixtures.scrubbed(fakes)))
+            .collect();
+        scrubbed
+    }
+}
+
+/// The key under which the outcome of a call is recorded: the service and operation, followed
+/// by whatever the call depends on, e.g. "pricing:GetProducts:eu-west-1:m5.large".
+pub fn call_key(operation: &str, parameters: &[&str]) -> String {
+    let mut parts = vec![operation];
+    parts.extend(parameters);
+    parts.join(":")
+}
+
+/// The account IDs in all the ARNs in some text.
+fn arn_account_ids(text: &str) -> Vec<String> {
+    text.match_indices("arn:")
+        .filter_map(|(start, _)| arn_account(&text[start..]).map(|account| text[start..][account].to_string()))
+        .collect()
+}
+
+/// Replace the account IDs in all the ARNs in some text with their fakes.
+fn scrub_arns(text: &str, fakes: &HashMap<String, String>) -> String {
+    let mut scrubbed = String::with_capacity(text.len());
+    let mut copied = 0;
+
+    for (start, _) in text.match_indices("arn:") {
+        if let Some(account) = arn_account(&text[start..]) {
+            let account = start + account.start..start + account.end;
+            if let Some(fake) = fakes.get(&text[account.clone()]).filter(|_| account.start >= copied) {
+                scrubbed.push_str(&text[copied..account.start]);
+                scrubbed.push_str(fake);
+                copied = account.end;
+            }
+        }
+    }
+
+    scrubbed.push_str(&text[copied..]);
+    scrubbed
+}
+
+/// Where the account ID is in an ARN at the start of some text, if it has one.  ARNs look
+/// like `arn:partition:service:region:account:resource`.
+fn arn_account(arn: &str) -> Option<Range<usize>> {
+    let start = arn.match_indices(':').nth(3)?.0 + 1;
+    let end = start + arn[start..].find(':')?;
+    let account = &arn[start..end];
+    (account.len() == 12 && account.chars().all(|c| c.is_ascii_digit())).then_some(start..end)
+}
+
+/// Read the fixtures of other accounts, in `<dir>/accounts/<account ID>`.
+fn read_account_dirs(dir: &Path) -> Result<BTreeMap<String, FixtureSet>, JawsError> {
+    let mut accounts = BTreeMap::new();
+
+    for entry in fs::read_dir(dir.join("accounts")).into_iter().flatten().flatten() {
+        if entry.path().is_dir() {
+            accounts.insert(entry.file_name().to_string_lossy().to_string(), FixtureSet::load_dir(&entry.path())?);
+        }
+    }
+
+    Ok(accounts)
+}
+
+/// Read `<dir>/<name>.json`, or None if it doesn't exist.
+fn read_fixture<T: DeserializeOwned>(dir: &Path, name: &str) -> Result<Option<T>, JawsError> {
+    let path = dir.join(format!("{}.json", name));
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let json = fs::read_to_string(&path).map_err(|e| JawsError::with_kind(JawsErrorKind::Config,
+        format!("Couldn't read the fixture file '{}': {}", path.display(), e)).with_source(e))?;
+    serde_json::from_str(&json).map(Some).map_err(|e| JawsError::with_kind(JawsErrorKind::Config,
+        format!("The fixture file '{}' isn't valid: {}", path.display(), e)).with_source(e))
+}
+
+/// Whether something with the given region and availability zone is in `region`.  Anything
+/// with neither is taken to be in every region.
+pub fn in_region(own_region: Option<&str>, az: Option<&str>, region: &str) -> bool {
+    match (own_region, az) {
+        (Some(own_region), _) => own_region == region,
+        (None, Some(az)) => az.starts_with(region),
+        (None, None) => true,
+    }
+}
+
+// Conversions to the SDK types the rest of JAWS works with, and from them when recording.
+
+impl IdentityFixture {
+    pub fn from_sdk(identity: &GetCallerIdentityOutput, region: &str) -> Self {
+        Self {
+            account: identity.account().unwrap_or_default().to_string(),
+            arn: identity.arn().unwrap_or_default().to_string(),
+            user_id: identity.user_id().unwrap_or_default().to_string(),
+            region: Some(region.to_string()),
+            account_alias: None,
+        }
+    }
+
+    pub fn to_sdk(&self) -> GetCallerIdentityOutput {
+        GetCallerIdentityOutput::builder()
+            .account(&self.account)
+            .arn(&self.arn)
+            .user_id(&self.user_id)
+            .build()
+    }
+}
+
+impl InstanceFixture {
+    pub fn from_sdk(instance: &Instance) -> Self {
+        Self {
+            instance_id: instance.instance_id().unwrap_or_default().to_string(),
+            state: instance.state().and_then(|s| s.name()).map(|n| n.as_str()).unwrap_or_default().to_string(),
+            instance_type: instance.instance_type().map(|t| t.as_str().to_string()),
+            availability_zone: instance.placement().and_then(|p| p.availability_zone()).map(|az| az.to_string()),
+            public_ip: instance.public_ip_address().map(|ip| ip.to_string()),
+            private_ip: instance.private_ip_address().map(|ip| ip.to_string()),
+            spot_instance_request_id: instance.spot_instance_request_id().map(|id| id.to_string()),
+            instance_profile_arn: instance.iam_instance_profile().and_then(|p| p.arn()).map(|arn| arn.to_string()),
+            vpc_id: instance.vpc_id().map(|id| id.to_string()),
+            tags: instance.tags().iter()
+                .filter_map(|tag| Some((tag.key()?.to_string(), tag.value().unwrap_or_default().to_string())))
+                .collect(),
+        }
+    }
+
+    /// Whether the instance matches a `DescribeInstances` filter, as far as the fixture
+    /// says.  Filters the fixtures can't answer match nothing.
+    pub fn matches(&self, filter: &Filter) -> bool {
+        let field: Option<&str> = match filter.name().unwrap_or_default() {
+            "instance-state-name" => Some(&self.state),
+            "instance-type" => self.instance_type.as_deref(),
+            "availability-zone" => self.availability_zone.as_deref(),
+            "vpc-id" => self.vpc_id.as_deref(),
+            "instance-id" => Some(&self.instance_id),
+            "tag-key" => return filter.values().iter().any(|key| self.tags.keys().any(|k| glob_match(key, k))),
+            name => name.strip_prefix("tag:").and_then(|key| self.tags.get(key)).map(|v| v.as_str()),
+        };
+
+        field.is_some_and(|field| filter.values().iter().any(|value| glob_match(value, field)))
+    }
+
+    pub fn to_sdk(&self) -> Instance {
+        Instance::builder()
+            .instance_id(&self.instance_id)
+            .state(InstanceState::builder().name(InstanceStateName::from(self.state.as_str())).build())
+            .set_instance_type(self.instance_type.as_deref().map(InstanceType::from))
+            .set_placement(self.availability_zone.as_ref()
+                .map(|az| Placement::builder().availability_zone(az).build()))
+            .set_public_ip_address(self.public_ip.clone())
+            .set_private_ip_address(self.private_ip.clone())
+            .set_spot_instance_request_id(self.spot_instance_request_id.clone())
+            .set_iam_instance_profile(self.instance_profile_arn.as_ref()
+                .map(|arn| IamInstanceProfile::builder().arn(arn).build()))
+            .set_vpc_id(self.vpc_id.clone())
+            .set_tags(Some(self.tags.iter()
+                .map(|(key, value)| Tag::builder().key(key).value(value).build())
+                .collect()))
+            .build()
+    }
+}
+
+impl ReservationFixture {
+    /// `region` is where the reservation was found; it's kept only for regional reservations.
+    pub fn from_sdk(reservation: &ReservedInstances, region: &str) -> Self {
+        let availability_zone = reservation.availability_zone().map(|az| az.to_string());
+
+        Self {
+            reserved_instances_id: reservation.reserved_instances_id().unwrap_or_default().to_string(),
+            instance_type: reservation.instance_type().map(|t| t.as_str()).unwrap_or_default().to_string(),
+            instance_count: reservation.instance_count().unwrap_or_default(),
+            region: availability_zone.is_none().then(|| region.to_string()),
+            availability_zone,
+            end: reservation.end().and_then(|end| end.to_chrono_utc().ok()).unwrap_or_default(),
+            duration_seconds: reservation.duration().unwrap_or_default(),
+            offering_type: reservation.offering_type().map(|o| o.as_str()).unwrap_or_default().to_string(),
+            fixed_price: reservation.fixed_price().unwrap_or_default(),
+            recurring_charges: reservation.recurring_charges().iter()
+                .map(|charge| RecurringChargeFixture {
+                    frequency: charge.frequency().map(|f| f.as_str()).unwrap_or_default().to_string(),
+                    amount: charge.amount().unwrap_or_default(),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn to_sdk(&self) -> ReservedInstances {
+        ReservedInstances::builder()
+            .reserved_instances_id(&self.reserved_instances_id)
+            .instance_type(InstanceType::from(self.instance_type.as_str()))
+            .instance_count(self.instance_count)
+            .set_availability_zone(self.availability_zone.clone())
+            .end(AwsDateTime::from_secs(self.end.timestamp()))
+            .duration(self.duration_seconds)
+            .offering_type(OfferingTypeValues::from(self.offering_type.as_str()))
+            .fixed_price(self.fixed_price)
+            .set_recurring_charges(Some(self.recurring_charges.iter()
+                .map(|charge| RecurringCharge::builder()
+                    .frequency(RecurringChargeFrequency::from(charge.frequency.as_str()))
+                    .amount(charge.amount)
+                    .build())
+                .collect()))
+            .build()
+    }
+}
+
+impl InstanceProfileFixture {
+    pub fn from_sdk(profile: &InstanceProfile) -> Self {
+        Self {
+            arn: profile.arn().to_string(),
+            name: profile.instance_profile_name().to_string(),
+            roles: profile.roles().iter().map(|role| role.role_name().to_string()).collect(),
+        }
+    }
+
+    pub fn to_sdk(&self) -> Result<InstanceProfile, JawsError> {
+        let invalid = |e: aws_sdk_iam::error::BuildError| JawsError::with_kind(JawsErrorKind::Config,
+            format!("The fixture for instance profile '{}' isn't valid: {}", self.arn, e)).with_source(e);
+
+        // IAM requires fields JAWS doesn't use, so they're made up.
+        let created = AwsDateTime::from_secs(0);
+        let account = self.arn.split(':').nth(4).unwrap_or_default();
+
+        let roles = self.roles.iter()
+            .map(|role| Role::builder()
+                .path("/")
+                .role_name(role)
+                .role_id(role)
+                .arn(format!("arn:aws:iam::{}:role/{}", account, role))
+                .create_date(created)
+                .build())
+            .collect::<Result<Vec<Role>, _>>()
+            .map_err(invalid)?;
+
+        InstanceProfile::builder()
+            .path("/")
+            .instance_profile_name(&self.name)
+            .instance_profile_id(&self.name)
+            .arn(&self.arn)
+            .create_date(created)
+            .set_roles(Some(roles))
+            .build()
+            .map_err(invalid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scrubs_only_account_ids() {
+        let fixtures = FixtureSet {
+            identity: Some(IdentityFixture {
+                account: "111122223333".to_string(),
+                arn: "arn:aws:iam::111122223333:user/demo".to_string(),
+                ..Default::default()
+            }),
+            instances: vec![InstanceFixture {
+                instance_id: "i-0123456789012".to_string(),
+                instance_profile_arn: Some("arn:aws:iam::444455556666:instance-profile/web".to_string()),
+                tags: BTreeMap::from([("cost-centre".to_string(), "111122223333".to_string())]),
+                ..Default::default()
+            }],
+            reserved_instances: vec![ReservationFixture {
+                reserved_instances_id: "ri-111122223333".to_string(),
+                ..Default::default()
+            }],
+            failures: BTreeMap::from([("sts:AssumeRole".to_string(), FailureFixture {
+                kind: JawsErrorKind::Credentials,
+                message: "arn:aws:iam::000000000001:role/x can't assume arn:aws:iam::444455556666:role/y".to_string(),
+            })]),
+            accounts: BTreeMap::from([("444455556666".to_string(), FixtureSet::default())]),
+            ..Default::default()
+        };
+
+        let scrubbed = fixtures.scrub_account_ids();
+
+        let identity = scrubbed.identity.unwrap();
+        assert_eq!(identity.account, "000000000001");
+        assert_eq!(identity.arn, "arn:aws:iam::000000000001:user/demo");
+        assert_eq!(scrubbed.instances[0].instance_profile_arn.as_deref(),
+                   Some("arn:aws:iam::000000000002:instance-profile/web"));
+        assert_eq!(scrubbed.accounts.keys().collect::<Vec<_>>(), ["000000000002"]);
+        // A real ID which looks like a fake one gets a fake of its own.
+        assert_eq!(scrubbed.failures["sts:AssumeRole"].message,
+                   "arn:aws:iam::000000000003:role/x can't assume arn:aws:iam::000000000002:role/y");
+
+        // Other values which happen to contain an account ID are left alone.
+        assert_eq!(scrubbed.instances[0].instance_id, "i-0123456789012");
+        assert_eq!(scrubbed.instances[0].tags["cost-centre"], "111122223333");
+        assert_eq!(scrubbed.reserved_instances[0].reserved_instances_id, "ri-111122223333");
+    }
+}
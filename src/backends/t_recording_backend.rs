@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use async_trait::async_trait;
+use aws_sdk_ec2::types::{Filter, Instance, ReservedInstances};
+use aws_sdk_iam::types::InstanceProfile;
+use aws_sdk_sts::operation::get_caller_identity::GetCallerIdentityOutput;
+
+use crate::backends::t_fixture_set::{call_key, in_region, FailureFixture, FixtureSet, IdentityFixture, InstanceFixture,
+                                     InstanceProfileFixture, ReservationFixture};
+use crate::backends::tr_aws_backend::{AwsBackend, Ec2Api, IamApi, PricingApi, StsApi, EC2_DESCRIBE_INSTANCES,
+                                      EC2_DESCRIBE_INSTANCE_TYPES, EC2_DESCRIBE_REGIONS, EC2_DESCRIBE_RESERVED_INSTANCES,
+                                      IAM_LIST_ACCOUNT_ALIASES, IAM_LIST_ATTACHED_ROLE_POLICIES, IAM_LIST_INSTANCE_PROFILES,
+                                      PRICING_GET_PRODUCTS, STS_GET_CALLER_IDENTITY, instance_action_operation};
+use crate::e_instance_action::InstanceAction;
+use crate::errors::jaws_error::JawsError;
+use crate::t_ec2_instance::InstanceSpec;
+
+/// A backend which passes every call on to another backend, and records what comes back -
+/// data or failure - in a file (`--record`).  The file can be replayed with `--replay`, which
+/// serves the same responses through a `FixtureBackend`.
+///
+/// The recording is kept in memory and written once, by `save_recordings`, when the run
+/// finishes - successfully or not, so that a run which fails part way still leaves a recording
+/// of everything up to the failure.  When a run uses several regions or accounts, each has its
+/// own backend, and they share the recording.  Other accounts' data is recorded under their
+/// account ID.
+pub struct RecordingBackend {
+    inner: Box<dyn AwsBackend>,
+    region: String,
+    /// The account, if a role in another account was assumed.
+    account: Option<String>,
+    recording: Arc<Mutex<FixtureSet>>,
+}
+
+/// The recordings being made in this run, by file.
+static RECORDINGS: OnceLock<Mutex<HashMap<PathBuf, Arc<Mutex<FixtureSet>>>>> = OnceLock::new();
+
+impl RecordingBackend {
+    /// Start recording calls to `inner`, which works in `region`, and in `account` if a role
+    /// was assumed.  The caller identity is recorded straight away, so that every recording
+    /// says where it came from.
+    pub async fn start(inner: Box<dyn AwsBackend>, region: &str, account: Option<String>, path: PathBuf)
+                       -> Result<Self, JawsError> {
+        let backend = Self {
+            inner,
+            region: region.to_string(),
+            account,
+            recording: RECORDINGS.get_or_init(Default::default)
+                .lock().unwrap()
+                .entry(path)
+                .or_default()
+                .clone(),
+        };
+
+        backend.get_caller_identity().await?;
+        Ok(backend)
+    }
+
+    /// Record the outcome of a call.  A successful result is added with `add`; a failure is
+    /// recorded under `key`.
+    fn record<T>(&self, key: String, result: &Result<T, JawsError>, add: impl FnOnce(&mut FixtureSet, &T)) {
+        let mut recording = self.recording.lock().unwrap();
+
+        let fixtures = match &self.account {
+            Some(account) => recording.accounts.entry(account.clone()).or_default(),
+            None => &mut recording,
+        };
+        match result {
+            Ok(value) => {
+                fixtures.failures.remove(&key);
+                add(fixtures, value);
+            }
+            Err(e) => {
+                fixtures.failures.insert(key, FailureFixture { kind: e.kind(), message: e.to_string() });
+            }
+        }
+    }
+}
+
+/// Write every recording made in this run to its file, with account IDs replaced by made-up
+/// ones if `scrub_account_ids` is set.
+pub fn save_recordings(scrub_account_ids: bool) -> Result<(), JawsError> {
+    let Some(recordings) = RECORDINGS.get() else {
+        return Ok(());
+    };
+
+    for (path, recording) in recordings.lock().unwrap().iter() {
+        let recording = recording.lock().unwrap();
+        if scrub_account_ids {
+            recording.scrub_account_ids().save_file(path)?;
+        } else {
+            recording.save_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl StsApi for RecordingBackend {
+    async fn get_caller_identity(&self) -> Result<GetCallerIdentityOutput, JawsError> {
+        let result = self.inner.get_caller_identity().await;
+        self.record(call_key(STS_GET_CALLER_IDENTITY, &[]), &result, |recording, identity| {
+            // The first region recorded is the one replayed by default.
+            if recording.identity.is_none() {
+                recording.identity = Some(IdentityFixture::from_sdk(identity, &self.region));
+            }
+        });
+        result
+    }
+}
+
+#[async_trait]
+impl Ec2Api for RecordingBackend {
+    async fn describe_instances(&self, filters: &[Filter]) -> Result<Vec<Instance>, JawsError> {
+        let result = self.inner.describe_instances(filters).await;
+        self.record(call_key(EC2_DESCRIBE_INSTANCES, &[&self.region]), &result, |recording, instances| {
+            // Only an unfiltered call says which instances the region has; a filtered one
+            // updates or adds the instances it returns, and leaves the rest alone.
+            if filters.is_empty() {
+                recording.instances.retain(|i| !in_region(None, i.availability_zone.as_deref(), &self.region));
+            } else {
+                recording.instances.retain(|i| !instances.iter().any(|r| r.instance_id() == Some(i.instance_id.as_str())));
+            }
+            recording.instances.extend(instances.iter().map(InstanceFixture::from_sdk));
+        });
+        result
+    }
+
+    async fn describe_active_reserved_instances(&self) -> Result<Vec<ReservedInstances>, JawsError> {
+        let result = self.inner.describe_active_reserved_instances().await;
+        self.record(call_key(EC2_DESCRIBE_RESERVED_INSTANCES, &[&self.region]), &result, |recording, reservations| {
+            recording.reserved_instances
+                .retain(|r| !in_region(r.region.as_deref(), r.availability_zone.as_deref(), &self.region));
+            recording.reserved_instances.extend(reservations.iter()
+                .map(|r| ReservationFixture::from_sdk(r, &self.region)));
+        });
+        result
+    }
+
+    async fn describe_instance_types(&self) -> Result<HashMap<String, InstanceSpec>, JawsError> {
+        let result = self.inner.describe_instance_types().await;
+        self.record(call_key(EC2_DESCRIBE_INSTANCE_TYPES, &[&self.region]), &result, |recording, specs| {
+            recording.instance_types.extend(specs.iter().map(|(name, spec)| (name.clone(), *spec)));
+        });
+        result
+    }
+
+    async fn describe_regions(&self) -> Result<Vec<String>, JawsError> {
+        let result = self.inner.describe_regions().await;
+        self.record(call_key(EC2_DESCRIBE_REGIONS, &[]), &result, |recording, regions| {
+            recording.regions = regions.clone();
+        });
+        result
+    }
+
+    /// Only a failure is recorded; the new states show up in later `DescribeInstances` calls.
+    async fn change_instance_state(&self, action: InstanceAction, instance_ids: &[String], dry_run: bool)
+                                   -> Result<(), JawsError> {
+        let result = self.inner.change_instance_state(action, instance_ids, dry_run).await;
+        self.record(call_key(instance_action_operation(action), &[&self.region]), &result, |_, _| {});
+        result
+    }
+}
+
+#[async_trait]
+impl IamApi for RecordingBackend {
+    async fn list_instance_profiles(&self) -> Result<Vec<InstanceProfile>, JawsError> {
+        let result = self.inner.list_instance_profiles().await;
+        self.record(call_key(IAM_LIST_INSTANCE_PROFILES, &[]), &result, |recording, profiles| {
+            recording.instance_profiles = profiles.iter().map(InstanceProfileFixture::from_sdk).collect();
+        });
+        result
+    }
+
+    async fn list_attached_role_policies(&self, role_name: &str) -> Result<Vec<String>, JawsError> {
+        let result = self.inner.list_attached_role_policies(role_name).await;
+        self.record(call_key(IAM_LIST_ATTACHED_ROLE_POLICIES, &[role_name]), &result, |recording, policies| {
+            recording.role_policies.insert(role_name.to_string(), policies.clone());
+        });
+        result
+    }
+
+    async fn get_account_alias(&self) -> Result<Option<String>, JawsError> {
+        let result = self.inner.get_account_alias().await;
+        self.record(call_key(IAM_LIST_ACCOUNT_ALIASES, &[]), &result, |recording, alias| {
+            if let Some(identity) = &mut recording.identity {
+                identity.account_alias = alias.clone();
+            }
+        });
+        result
+    }
+}
+
+#[async_trait]
+impl PricingApi for RecordingBackend {
+    async fn get_odm_rate(&self, region: &str, instance_type: &str) -> Result<f32, JawsError> {
+        let result = self.inner.get_odm_rate(region, instance_type).await;
+        self.record(call_key(PRICING_GET_PRODUCTS, &[region, instance_type]), &result, |recording, rate| {
+            recording.prices.entry(region.to_string()).or_default().insert(instance_type.to_string(), *rate);
+        });
+        result
+    }
+}
@@ -0,0 +1,381 @@
+// https://awslabs.github.io/aws-sdk-rust/
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use async_trait::async_trait;
+use aws_config::sts::AssumeRoleProvider;
+use aws_config::{BehaviorVersion, Region, SdkConfig};
+use aws_credential_types::provider::SharedCredentialsProvider;
+use aws_sdk_ec2::error::{ProvideErrorMetadata, SdkError};
+use aws_sdk_ec2::types::{Filter, Instance, ReservedInstances};
+use aws_sdk_iam::types::InstanceProfile;
+use aws_sdk_pricing::types;
+use aws_sdk_pricing::types::FilterType;
+use aws_sdk_sts::operation::get_caller_identity::GetCallerIdentityOutput;
+use serde_json::Value;
+
+use crate::backends::t_service_endpoint::SERVICES;
+use crate::backends::tr_aws_backend::{Ec2Api, IamApi, PricingApi, StsApi};
+use crate::e_instance_action::InstanceAction;
+use crate::errors::jaws_error::{JawsError, JawsErrorKind};
+use crate::t_ec2_instance::InstanceSpec;
+use crate::textutils::Textutil;
+use crate::Options;
+
+const TYPE_BATCH_SIZE: i32 = 100;
+const INSTANCE_BATCH_SIZE: i32 = 1000;
+
+/// The real AWS backend, which calls the services through the SDK.
+///
+/// Configuration (region and credentials provider) is resolved once, when the backend is
+/// created, and each service client is created from it the first time it's needed.  With a role
+/// to assume, every call is made in that role's account.  Each client uses the endpoint URL
+/// given for its service, if any (`--endpoint-url`, `--service-endpoint`).
+pub struct SdkBackend {
+    sdk_config: SdkConfig,
+    endpoint_urls: HashMap<&'static str, String>,
+    sts_client: OnceLock<aws_sdk_sts::Client>,
+    ec2_client: OnceLock<aws_sdk_ec2::Client>,
+    iam_client: OnceLock<aws_sdk_iam::Client>,
+    pricing_client: OnceLock<aws_sdk_pricing::Client>,
+    textutil: Textutil,
+}
+
+impl SdkBackend {
+    /// Resolve the AWS configuration.  Returns the backend and the region it works in.
+    /// Fails with a configuration error if no region can be determined.
+    pub async fn new(options: &Options) -> Result<(Self, String), JawsError> {
+        // Use the profile and region from options, if Some.  If None, they're found using AWS
+        // defaulting.  The profile also supplies the region, unless one is given.
+        let mut loader = aws_config::defaults(BehaviorVersion::latest());
+        if let Some(profile) = &options.profile {
+            loader = loader.profile_name(profile);
+        }
+        if let Some(region) = &options.region {
+            loader = loader.region(Region::new(region.clone()));
+        }
+        let mut sdk_config = loader.load().await;
+
+        let endpoint_urls: HashMap<&'static str, String> = SERVICES.iter()
+            .filter_map(|service| options.endpoint_url(service).map(|url| (*service, url.to_string())))
+            .collect();
+
+        // To work in another account, the role is assumed using the credentials found above.
+        if let Some(role_arn) = &options.assume_role {
+            let mut sts_config = sdk_config.to_builder();
+            if let Some(url) = endpoint_urls.get("sts") {
+                sts_config.set_endpoint_url(Some(url.clone()));
+            }
+            let provider = AssumeRoleProvider::builder(role_arn)
+                .session_name("jaws")
+                .configure(&sts_config.build())
+                .build()
+                .await;
+            sdk_config = sdk_config.into_builder()
+                .credentials_provider(SharedCredentialsProvider::new(provider))
+                .build();
+        }
+
+        let region = match sdk_config.region() {
+            None => {
+                return Err(JawsError::with_kind(JawsErrorKind::Config,
+                    "No AWS region could be calculated from your configuration.\n\nEnsure AWS_REGION and/or AWS_PROFILE are set, or use --region or --profile.".to_string()));
+            }
+            Some(region) => region.to_string(),
+        };
+
+        let backend = Self {
+            sdk_config,
+            endpoint_urls,
+            sts_client: OnceLock::new(),
+            ec2_client: OnceLock::new(),
+            iam_client: OnceLock::new(),
+            pricing_client: OnceLock::new(),
+            textutil: Textutil::new(options),
+        };
+
+        Ok((backend, region))
+    }
+
+    /// The endpoint URL given for `service`.  Without one, the client uses whatever the AWS
+    /// configuration says, e.g. `AWS_ENDPOINT_URL_EC2`.
+    fn endpoint_url(&self, service: &str) -> Option<String> {
+        self.endpoint_urls.get(service).cloned()
+    }
+
+    fn sts(&self) -> &aws_sdk_sts::Client {
+        self.sts_client.get_or_init(|| {
+            let mut config = aws_sdk_sts::config::Builder::from(&self.sdk_config);
+            if let Some(url) = self.endpoint_url("sts") {
+                config.set_endpoint_url(Some(url));
+            }
+            aws_sdk_sts::Client::from_conf(config.build())
+        })
+    }
+
+    fn ec2(&self) -> &aws_sdk_ec2::Client {
+        self.ec2_client.get_or_init(|| {
+            let mut config = aws_sdk_ec2::config::Builder::from(&self.sdk_config);
+            if let Some(url) = self.endpoint_url("ec2") {
+                config.set_endpoint_url(Some(url));
+            }
+            aws_sdk_ec2::Client::from_conf(config.build())
+        })
+    }
+
+    fn iam(&self) -> &aws_sdk_iam::Client {
+        self.iam_client.get_or_init(|| {
+            let mut config = aws_sdk_iam::config::Builder::from(&self.sdk_config);
+            if let Some(url) = self.endpoint_url("iam") {
+                config.set_endpoint_url(Some(url));
+            }
+            aws_sdk_iam::Client::from_conf(config.build())
+        })
+    }
+
+    /// AWS Pricing is not available everywhere - we use eu-central-1 to access it, whatever
+    /// the region being priced, unless it has its own endpoint URL.
+    fn pricing(&self) -> &aws_sdk_pricing::Client {
+        self.pricing_client.get_or_init(|| {
+            let mut config = aws_sdk_pricing::config::Builder::from(&self.sdk_config)
+                .region(Region::from_static("eu-central-1"));
+            if let Some(url) = self.endpoint_url("pricing") {
+                config.set_endpoint_url(Some(url));
+            }
+            aws_sdk_pricing::Client::from_conf(config.build())
+        })
+    }
+}
+
+#[async_trait]
+impl StsApi for SdkBackend {
+    async fn get_caller_identity(&self) -> Result<GetCallerIdentityOutput, JawsError> {
+        self.sts()
+            .get_caller_identity()
+            .send()
+            .await
+            .map_err(|e| JawsError::from_sdk("Couldn't get the caller identity", e))
+    }
+}
+
+#[async_trait]
+impl Ec2Api for SdkBackend {
+    async fn describe_instances(&self, filters: &[Filter]) -> Result<Vec<Instance>, JawsError> {
+        let mut instances: Vec<Instance> = Vec::new();
+
+        let mut pages = self.ec2().describe_instances()
+            .set_filters(Some(filters.to_vec()))
+            .max_results(INSTANCE_BATCH_SIZE)
+            .into_paginator()
+            .send();
+
+        while let Some(page) = pages.next().await {
+            let page = page.map_err(|e| JawsError::from_sdk("Couldn't list EC2 instances", e))?;
+            for reservation in page.reservations() {
+                instances.extend(reservation.instances().iter().cloned());
+            }
+            self.textutil.notify_comms(Some(format!("getting instances [{}]", instances.len())));
+        }
+
+        Ok(instances)
+    }
+
+    /// `DescribeReservedInstances` isn't paginated: it always returns every matching
+    /// reservation in a single response.
+    async fn describe_active_reserved_instances(&self) -> Result<Vec<ReservedInstances>, JawsError> {
+        let result = self.ec2()
+            .describe_reserved_instances()
+            .filters(Filter::builder().name("state").values("active").build())
+            .send()
+            .await;
+
+        match result {
+            Ok(resp) => Ok(resp.reserved_instances.unwrap_or_default()),
+            Err(error) => Err(JawsError::from_sdk("Couldn't list EC2 reservations", error)),
+        }
+    }
+
+    async fn describe_instance_types(&self) -> Result<HashMap<String, InstanceSpec>, JawsError> {
+        let mut specmap: HashMap<String, InstanceSpec> = HashMap::new();
+
+        let mut pages = self.ec2()
+            .describe_instance_types()
+            .max_results(TYPE_BATCH_SIZE)
+            .into_paginator()
+            .send();
+
+        while let Some(page) = pages.next().await {
+            let page = page.map_err(|e| JawsError::from_sdk("Couldn't describe EC2 instance types", e))?;
+
+            for t in page.instance_types() {
+                // Types without a CPU or memory description are skipped, and show as unknown.
+                let vcpus = t.v_cpu_info().and_then(|i| i.default_v_cpus());
+                let memory_mib = t.memory_info().and_then(|i| i.size_in_mib());
+
+                if let (Some(key), Some(vcpus), Some(memory_mib)) = (t.instance_type(), vcpus, memory_mib) {
+                    specmap.insert(
+                        key.as_str().to_string(),
+                        InstanceSpec {
+                            vcpus,
+                            memory_gib: memory_mib / 1024,
+                        },
+                    );
+                }
+            }
+
+            self.textutil.notify_comms(Some(format!("getting instance types [{}]", specmap.len())));
+        }
+
+        Ok(specmap)
+    }
+
+    async fn describe_regions(&self) -> Result<Vec<String>, JawsError> {
+        let result = self.ec2()
+            .describe_regions()
+            .send()
+            .await
+            .map_err(|e| JawsError::from_sdk("Couldn't list the enabled regions", e))?;
+
+        let mut regions: Vec<String> = result.regions().iter()
+            .filter_map(|r| r.region_name())
+            .map(|name| name.to_string())
+            .collect();
+        regions.sort();
+        Ok(regions)
+    }
+
+    async fn change_instance_state(&self, action: InstanceAction, instance_ids: &[String], dry_run: bool)
+                                   -> Result<(), JawsError> {
+        let ids = Some(instance_ids.to_vec());
+        let context = format!("Couldn't {} the instances", action);
+
+        match action {
+            InstanceAction::Start => action_result(&context, dry_run,
+                self.ec2().start_instances().set_instance_ids(ids).dry_run(dry_run).send().await),
+            InstanceAction::Stop | InstanceAction::Hibernate => action_result(&context, dry_run,
+                self.ec2().stop_instances().set_instance_ids(ids)
+                    .hibernate(action == InstanceAction::Hibernate)
+                    .dry_run(dry_run).send().await),
+            InstanceAction::Reboot => action_result(&context, dry_run,
+                self.ec2().reboot_instances().set_instance_ids(ids).dry_run(dry_run).send().await),
+            InstanceAction::Terminate => action_result(&context, dry_run,
+                self.ec2().terminate_instances().set_instance_ids(ids).dry_run(dry_run).send().await),
+        }
+    }
+}
+
+/// The outcome of an instance action.  A dry run which would have succeeded "fails" with
+/// `DryRunOperation`, which is success here.
+fn action_result<T, E, R>(context: &str, dry_run: bool, result: Result<T, SdkError<E, R>>) -> Result<(), JawsError>
+where
+    E: ProvideErrorMetadata + std::error::Error + Send + Sync + 'static,
+    R: std::fmt::Debug + Send + Sync + 'static,
+{
+    match result {
+        Ok(_) => Ok(()),
+        Err(e) if dry_run && e.code() == Some("DryRunOperation") => Ok(()),
+        Err(e) => Err(JawsError::from_sdk(context, e)),
+    }
+}
+
+#[async_trait]
+impl IamApi for SdkBackend {
+    async fn list_instance_profiles(&self) -> Result<Vec<InstanceProfile>, JawsError> {
+        let mut instance_profiles: Vec<InstanceProfile> = Vec::new();
+
+        let mut pages = self.iam().list_instance_profiles().into_paginator().send();
+
+        while let Some(page) = pages.next().await {
+            let page = page.map_err(|e| JawsError::from_sdk("Couldn't list IAM instance profiles", e))?;
+            instance_profiles.extend(page.instance_profiles().iter().cloned());
+            self.textutil.notify_comms(Some(format!("filling Instance Profile cache [{}]", instance_profiles.len())));
+        }
+
+        Ok(instance_profiles)
+    }
+
+    async fn list_attached_role_policies(&self, role_name: &str) -> Result<Vec<String>, JawsError> {
+        let mut policy_names: Vec<String> = Vec::new();
+
+        let mut pages = self.iam()
+            .list_attached_role_policies()
+            .role_name(role_name)
+            .into_paginator()
+            .send();
+
+        while let Some(page) = pages.next().await {
+            let page = page.map_err(|e| JawsError::from_sdk(
+                &format!("Couldn't list the policies of role {}", role_name), e))?;
+            policy_names.extend(page.attached_policies().iter()
+                .filter_map(|policy| policy.policy_name())
+                .map(|name| name.to_string()));
+        }
+
+        Ok(policy_names)
+    }
+
+    async fn get_account_alias(&self) -> Result<Option<String>, JawsError> {
+        // An account has at most one alias.
+        let result = self.iam()
+            .list_account_aliases()
+            .send()
+            .await
+            .map_err(|e| JawsError::from_sdk("Couldn't get the account alias", e))?;
+
+        Ok(result.account_aliases().first().cloned())
+    }
+}
+
+#[async_trait]
+impl PricingApi for SdkBackend {
+    async fn get_odm_rate(&self, region: &str, instance_type: &str) -> Result<f32, JawsError> {
+        let result = self.pricing()
+            .get_products()
+            .filters(term_match("instanceType", instance_type)?)
+            .filters(term_match("regionCode", region)?)
+            .filters(term_match("preInstalledSw", "NA")?)
+            .filters(term_match("productfamily", "compute instance")?)
+            .filters(term_match("tenancy", "Shared")?)
+            .filters(term_match("operatingSystem", "Linux")?)
+            .filters(term_match("capacityStatus", "Used")?)
+            .service_code("AmazonEC2")
+            .send()
+            .await
+            .map_err(|e| JawsError::from_sdk(&format!("Couldn't get the on-demand price of {}", instance_type), e))?;
+
+        if result.price_list().len() != 1 {
+            return Err(JawsError::with_kind(JawsErrorKind::NotFound, format!(
+                "The on-demand price search for {} returned {} results rather than exactly one",
+                instance_type, result.price_list().len())));
+        }
+
+        parse_odm_price(&result.price_list()[0]).ok_or_else(|| JawsError::new(format!(
+            "The on-demand price data for {} couldn't be understood", instance_type)))
+    }
+}
+
+/// A Pricing API filter requiring `field` to equal `value`.
+fn term_match(field: &str, value: &str) -> Result<types::Filter, JawsError> {
+    types::Filter::builder()
+        .r#type(FilterType::TermMatch)
+        .field(field)
+        .value(value)
+        .build()
+        .map_err(|e| JawsError::new(format!("Couldn't build pricing filter: {}", e)).with_source(e))
+}
+
+/// Extract the hourly USD price from a Pricing API product document.
+fn parse_odm_price(price_list_item: &str) -> Option<f32> {
+    let price_data: Value = serde_json::from_str(price_list_item).ok()?;
+
+    // This is a bit fiddly, because the name of the key after the tree node "OnDemand" is
+    // dynamic. `as_object()` converts it to a map, so we can get the first value.
+    let odm: &Value = price_data["terms"]["OnDemand"].as_object()?.values().next()?;
+
+    // ... And again.
+    let dimension: &Value = odm["priceDimensions"].as_object()?.values().next()?;
+
+    // Get the final price out of the string.
+    dimension["pricePerUnit"]["USD"].as_str()?.parse::<f32>().ok()
+}
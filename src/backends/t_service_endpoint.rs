@@ -0,0 +1,45 @@
+use std::str::FromStr;
+
+/// The services JAWS talks to, by the names used with `--service-endpoint`.
+pub const SERVICES: [&str; 5] = ["sts", "ec2", "iam", "pricing", "ssm"];
+
+/// An endpoint URL to use for one AWS service in place of the real one, e.g. a LocalStack
+/// or moto server.
+#[derive(Clone, Debug)]
+pub struct ServiceEndpoint {
+    pub service: String,
+    pub url: String,
+}
+
+/// Parses `SERVICE=URL`, e.g. `pricing=http://localhost:4566`.
+impl FromStr for ServiceEndpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((service, url)) if SERVICES.contains(&service.to_lowercase().as_str()) && !url.is_empty() =>
+                Ok(ServiceEndpoint { service: service.to_lowercase(), url: url.to_string() }),
+            Some((service, _)) if !SERVICES.contains(&service.to_lowercase().as_str()) =>
+                Err(format!("'{}' isn't a service; use one of {}", service, SERVICES.join(", "))),
+            _ => Err(format!("'{}' isn't an endpoint; use SERVICE=URL, e.g. ec2=http://localhost:4566", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_service_and_url() {
+        let endpoint: ServiceEndpoint = "Pricing=http://localhost:5000/?a=b".parse().unwrap();
+        assert_eq!((endpoint.service.as_str(), endpoint.url.as_str()), ("pricing", "http://localhost:5000/?a=b"));
+    }
+
+    #[test]
+    fn rejects_unknown_services_and_missing_urls() {
+        assert!("s3=http://localhost:4566".parse::<ServiceEndpoint>().unwrap_err().contains("isn't a service"));
+        assert!("ec2=".parse::<ServiceEndpoint>().unwrap_err().contains("isn't an endpoint"));
+        assert!("http://localhost:4566".parse::<ServiceEndpoint>().unwrap_err().contains("isn't an endpoint"));
+    }
+}
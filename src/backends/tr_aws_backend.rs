@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use aws_sdk_ec2::types::{Filter, Instance, ReservedInstances};
+use aws_sdk_iam::types::InstanceProfile;
+use aws_sdk_sts::operation::get_caller_identity::GetCallerIdentityOutput;
+
+use crate::e_instance_action::InstanceAction;
+use crate::errors::jaws_error::JawsError;
+use crate::t_ec2_instance::InstanceSpec;
+
+// The AWS services JAWS uses, one trait per service.  `AWSHandler` talks to AWS only through
+// these, so that the real SDK can be swapped for canned data (see `Backend`).  Listings return
+// everything: implementations deal with pagination themselves.
+
+// The operations, as named in recordings.
+pub const STS_GET_CALLER_IDENTITY: &str = "sts:GetCallerIdentity";
+pub const EC2_DESCRIBE_INSTANCES: &str = "ec2:DescribeInstances";
+pub const EC2_DESCRIBE_RESERVED_INSTANCES: &str = "ec2:DescribeReservedInstances";
+pub const EC2_DESCRIBE_INSTANCE_TYPES: &str = "ec2:DescribeInstanceTypes";
+pub const EC2_DESCRIBE_REGIONS: &str = "ec2:DescribeRegions";
+pub const EC2_START_INSTANCES: &str = "ec2:StartInstances";
+pub const EC2_STOP_INSTANCES: &str = "ec2:StopInstances";
+pub const EC2_REBOOT_INSTANCES: &str = "ec2:RebootInstances";
+pub const EC2_TERMINATE_INSTANCES: &str = "ec2:TerminateInstances";
+pub const IAM_LIST_INSTANCE_PROFILES: &str = "iam:ListInstanceProfiles";
+pub const IAM_LIST_ATTACHED_ROLE_POLICIES: &str = "iam:ListAttachedRolePolicies";
+pub const IAM_LIST_ACCOUNT_ALIASES: &str = "iam:ListAccountAliases";
+pub const PRICING_GET_PRODUCTS: &str = "pricing:GetProducts";
+
+/// Security Token Service access.
+#[async_trait]
+pub trait StsApi {
+    async fn get_caller_identity(&self) -> Result<GetCallerIdentityOutput, JawsError>;
+}
+
+/// EC2 access.
+#[async_trait]
+pub trait Ec2Api {
+    /// The instances in the region which match every filter (all of them, with none).
+    async fn describe_instances(&self, filters: &[Filter]) -> Result<Vec<Instance>, JawsError>;
+
+    /// All active reservations in the region.
+    async fn describe_active_reserved_instances(&self) -> Result<Vec<ReservedInstances>, JawsError>;
+
+    /// The specification of every instance type offered in the region, by type name.
+    async fn describe_instance_types(&self) -> Result<HashMap<String, InstanceSpec>, JawsError>;
+
+    /// The names of the regions enabled for the account, in order.
+    async fn describe_regions(&self) -> Result<Vec<String>, JawsError>;
+
+    /// Start, stop, reboot, terminate or hibernate instances.  With `dry_run`, AWS only checks
+    /// that the request would be allowed, and nothing changes.
+    async fn change_instance_state(&self, action: InstanceAction, instance_ids: &[String], dry_run: bool)
+                                   -> Result<(), JawsError>;
+}
+
+/// The operation which carries out an instance action.  Hibernation is a kind of stop.
+pub fn instance_action_operation(action: InstanceAction) -> &'static str {
+    match action {
+        InstanceAction::Start => EC2_START_INSTANCES,
+        InstanceAction::Stop | InstanceAction::Hibernate => EC2_STOP_INSTANCES,
+        InstanceAction::Reboot => EC2_REBOOT_INSTANCES,
+        InstanceAction::Terminate => EC2_TERMINATE_INSTANCES,
+    }
+}
+
+/// IAM access.
+#[async_trait]
+pub trait IamApi {
+    async fn list_instance_profiles(&self) -> Result<Vec<InstanceProfile>, JawsError>;
+
+    /// The names of the managed policies attached to a role.
+    async fn list_attached_role_policies(&self, role_name: &str) -> Result<Vec<String>, JawsError>;
+
+    /// The account's alias, if it has one.
+    async fn get_account_alias(&self) -> Result<Option<String>, JawsError>;
+}
+
+/// Price List access.
+#[async_trait]
+pub trait PricingApi {
+    /// The Linux on-demand hourly rate for an instance type in a region, in USD.
+    async fn get_odm_rate(&self, region: &str, instance_type: &str) -> Result<f32, JawsError>;
+}
+
+/// Everything a backend must provide.
+pub trait AwsBackend: StsApi + Ec2Api + IamApi + PricingApi + Send + Sync {}
+
+impl<T: StsApi + Ec2Api + IamApi + PricingApi + Send + Sync> AwsBackend for T {}
@@ -0,0 +1,86 @@
+use async_trait::async_trait;
+use chrono::Utc;
+
+use crate::errors::jaws_error::{JawsError, JawsErrorKind};
+use crate::matrix_handlers::e_matrix_cell::MatrixCell;
+use crate::matrix_handlers::t_matrix_output::{Matrix, MatrixFooter, MatrixHeader, MatrixOutput, MatrixRowT, MatrixRowsT};
+use crate::t_command::Command;
+use crate::t_disk_cache::{cache_status, clear_cache, CacheEntryStatus, DiskCache};
+use crate::textutils::Textutil;
+use crate::{CacheAction, Options, SubCommands};
+
+/// Inspect or empty the on-disk cache of AWS reference data.  `status` produces a matrix with
+/// one row per cache file; `clear` removes them all.
+pub struct CacheCommand {
+    entries: Option<Vec<CacheEntryStatus>>,
+}
+
+impl CacheCommand {
+    pub fn new() -> Self {
+        Self { entries: None }
+    }
+}
+
+#[async_trait]
+impl Command for CacheCommand {
+    async fn run(&mut self, options: &mut Options) -> Result<(), JawsError> {
+        let root = DiskCache::root().ok_or_else(|| JawsError::with_kind(JawsErrorKind::Config,
+            "This platform has no cache directory.".to_string()))?;
+
+        match &options.subcommand {
+            SubCommands::CACHE { action: CacheAction::Status } => {
+                self.entries = Some(cache_status(&root));
+            }
+            SubCommands::CACHE { action: CacheAction::Clear } => {
+                let removed = clear_cache(&root)?;
+                Textutil::new(options).notify(format!("Removed {} cache file(s) from {}\n", removed, root.display()));
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn get_matrix_output(&mut self) -> Option<MatrixOutput> {
+        let entries = self.entries.as_ref()?;
+
+        let header: MatrixRowT = vec![
+            Some("Region".into()),
+            Some("Data".into()),
+            Some("Entries".into()),
+            Some("Fetched".into()),
+            Some("Age".into()),
+            Some("Fresh".into()),
+        ];
+
+        let mut rows: MatrixRowsT = vec![header];
+        for entry in entries {
+            rows.push(vec![
+                Some(entry.region.as_str().into()),
+                Some(entry.kind.name().into()),
+                entry.entries.map(|n| MatrixCell::Integer(n as i64)).or(Some(MatrixCell::Unknown)),
+                entry.fetched_at.map(MatrixCell::from),
+                entry.fetched_at.map(|f| MatrixCell::Duration(Utc::now() - f)),
+                Some(entry.is_fresh().into()),
+            ]);
+        }
+
+        let mut notes = vec![format!("Cache directory: {}",
+                                     DiskCache::root().map(|r| r.display().to_string()).unwrap_or_default())];
+        if entries.is_empty() {
+            notes.push("The cache is empty.".to_string());
+        }
+
+        Some(MatrixOutput {
+            matrix_header: Some(MatrixHeader { title: Some("Cache".to_string()), output_program_header: true }),
+            matrix_footer: Some(MatrixFooter { footer: None, output_program_footer: true }),
+            matrices: vec![Matrix {
+                header: Some(vec!["Cached Reference Data".to_string()]),
+                rows: Some(rows),
+                aggregate_rows: None,
+                notes: Some(notes),
+                first_rows_header: true,
+            }],
+        })
+    }
+}
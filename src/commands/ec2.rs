@@ -1,14 +1,17 @@
+use std::collections::{HashMap, HashSet};
+
 use async_trait::async_trait;
 use aws_sdk_ec2::types::Instance;
-use std::fmt::Display;
 
 use crate::errors::jaws_error::JawsError;
-use crate::matrix_handlers::t_matrix_output::{Matrix, MatrixAggregateValue, MatrixFooter, MatrixHeader, MatrixOutput, MatrixRowsT};
+use crate::matrix_handlers::e_matrix_cell::MatrixCell;
+use crate::matrix_handlers::t_matrix_output::{add_note, Matrix, MatrixAggregateFn, MatrixAggregateValue, MatrixFooter, MatrixHeader, MatrixOutput, MatrixRowT, MatrixRowsT};
 use crate::t_aws_handler::AWSHandler;
 use crate::t_command::Command;
-use crate::t_ec2_instance::EC2Instance;
+use crate::t_ec2_instance::{EC2Instance, InstanceSpec, Lookup};
 use crate::textutils::Textutil;
-use crate::Options;
+use crate::t_instance_filters::InstanceFilters;
+use crate::{Options, SubCommands};
 
 /// Run an EC2 command.  This type may also be called internally by other commands or
 /// functionality.  This type creates its own `AWSHandler`, which itself caches various
@@ -17,102 +20,132 @@ use crate::Options;
 pub struct EC2Command {
     instances: Vec<EC2Instance>,
     instance_filter: Option<Vec<String>>,
+    notes: Vec<String>,
     textutil: Textutil,
     handler: AWSHandler,
     extended_output: bool,
+    /// Tags to show as columns of their own.
+    tag_columns: Vec<String>,
+    /// Whether to show all tags, in one column.
+    all_tags: bool,
 }
 
 impl EC2Command {
-    pub async fn new(options: &Options) -> Self {
+    pub async fn new(options: &Options) -> Result<Self, JawsError> {
+        Ok(Self::with_handler(options, AWSHandler::new(options).await?))
+    }
+
+    /// Create the command with an existing handler, reusing its configuration, clients and
+    /// caches.
+    pub fn with_handler(options: &Options, handler: AWSHandler) -> Self {
+        let (tag_columns, all_tags) = match &options.subcommand {
+            SubCommands::EC2 { tag_columns, all_tags, .. } => (tag_columns.clone(), *all_tags),
+            _ => (Vec::new(), false),
+        };
+
         Self {
             instances: Vec::new(),
             instance_filter: None,
+            notes: Vec::new(),
             textutil: Textutil::new(options),
-            handler: AWSHandler::new(options).await,
+            handler,
             extended_output: options.wide,
+            tag_columns,
+            all_tags,
         }
     }
 
-    pub(crate) async fn run_with_filter(&mut self, instances: Vec<String>, options: &mut Options) {
+    pub(crate) async fn run_with_filter(&mut self, instances: Vec<String>, options: &mut Options) -> Result<(), JawsError> {
         self.instance_filter = Some(instances);
-        _ = self.run(options).await;
+        self.run(options).await
     }
 
     fn generate_matrix(&self) -> Matrix {
         // Header
-        let mut header: Vec<Option<Box<dyn Display>>> = Vec::new();
-        header.push(Some(Box::new("Instance ID".to_string())));
-        header.push(Some(Box::new("Name".to_string())));
-        header.push(Some(Box::new("Status".to_string())));
-        header.push(Some(Box::new("Public IP".to_string())));
-        header.push(Some(Box::new("Private IP".to_string())));
-        header.push(Some(Box::new("Spot".to_string())));
+        let mut header: MatrixRowT = vec![
+            Some("Instance ID".into()),
+            Some("Name".into()),
+            Some("Status".into()),
+            Some("Public IP".into()),
+            Some("Private IP".into()),
+            Some("Spot".into()),
+        ];
 
         if self.extended_output {
-            header.push(Some(Box::new("SSM".to_string())));
-            header.push(Some(Box::new("AZ".to_string())));
-            header.push(Some(Box::new("Type".to_string())));
-            header.push(Some(Box::new("Spec".to_string())));
+            header.push(Some("SSM".into()));
+            header.push(Some("AZ".into()));
+            header.push(Some("Type".into()));
+            header.push(Some("Spec".into()));
+        }
+
+        // Tags are named as they are, unless that would clash with another column.
+        for tag in &self.tag_columns {
+            let clashes = header.iter().flatten().any(|h| h.to_string().eq_ignore_ascii_case(tag));
+            header.push(Some(if clashes { format!("Tag:{}", tag) } else { tag.clone() }.into()));
+        }
+        if self.all_tags {
+            header.push(Some("Tags".into()));
         }
 
         // Generate row data
-        let mut main_rows: MatrixRowsT = Vec::new();
-        main_rows.push(header);
+        let mut main_rows: MatrixRowsT = vec![header];
 
         // Aggregate
-        let mut cpu_tot = 0;
-        let mut mem_tot = 0;
+        let mut cpu_tot: i64 = 0;
+        let mut mem_tot: i64 = 0;
 
         for instance in &self.instances {
-            let mut row: Vec<Option<Box<dyn Display>>> = Vec::new();
-
-            row.push(Some(Box::new(instance.instance.instance_id.clone().unwrap().to_string())));
-            row.push(Some(Box::new(instance.get_name())));
-            row.push(Some(Box::new(instance.instance.state.clone().unwrap().name.unwrap().to_string())));
-            row.push(Some(Box::new(instance.instance.public_ip_address.clone().unwrap_or("None".to_string()).to_string())));
-            row.push(Some(Box::new(instance.instance.private_ip_address.clone().unwrap().to_string())));
             let spot = instance.instance.spot_instance_request_id().is_some();
-            row.push(Some(Box::new(if spot { "Yes".to_string() } else { "No".to_string() })));
+            let mut row: MatrixRowT = vec![
+                instance.instance.instance_id.clone().map(MatrixCell::from),
+                Some(instance.get_name().into()),
+                instance.instance.state().and_then(|s| s.name()).map(|n| n.as_str().into()),
+                instance.instance.public_ip_address.clone().map(MatrixCell::from),
+                instance.instance.private_ip_address.clone().map(MatrixCell::from),
+                Some(spot.into()),
+            ];
 
             if self.extended_output {
-                row.push(Some(Box::new(match instance.ssm {
-                    None => { "-".to_string() }
-                    Some(ssm) => {
-                        match ssm {
-                            true => { "Yes".to_string() }
-                            false => { "No".to_string() }
-                        }
-                    }
-                })));
-                row.push(Some(Box::new(instance.az.clone().unwrap_or("Unknown".to_string()))));
-                row.push(Some(Box::new(instance.instance_type.clone().unwrap_or("Unknown".to_string()))));
-
-                let spec = instance.spec.clone();
-
-                if spec.is_some() {
-                    let mut parts = spec.as_ref().unwrap().split("/");
-                    cpu_tot = cpu_tot + parts.next().unwrap().parse::<i32>().unwrap();
-                    mem_tot = mem_tot + parts.next().unwrap().parse::<i32>().unwrap();
+                row.push(instance.ssm.map(|ssm| ssm.to_cell(|b| (*b).into())));
+                row.push(instance.az.clone().map(MatrixCell::from));
+                row.push(instance.instance_type.clone().map(MatrixCell::from));
+
+                if let Some(spec) = instance.spec.as_ref().and_then(|s| s.known()) {
+                    cpu_tot += spec.vcpus as i64;
+                    mem_tot += spec.memory_gib;
                 }
 
-                row.push(Some(Box::new(spec.unwrap_or("Unknown".to_string()))));
+                row.push(instance.spec.map(|spec| spec.to_cell(|spec| MatrixCell::List(vec![
+                    spec.vcpus.into(),
+                    spec.memory_gib.into(),
+                ]))));
+            }
+
+            for tag in &self.tag_columns {
+                row.push(instance.find_tag_value(tag).map(MatrixCell::from));
+            }
+            if self.all_tags {
+                row.push(Some(MatrixCell::Map(instance.tags().into_iter()
+                    .map(|(key, value)| (key, value.into()))
+                    .collect())));
             }
 
             main_rows.push(row);
         }
 
         // Aggregate rows
-
-        
-        let mut aggregate_rows: Vec<MatrixAggregateValue> = Vec::new();
-        aggregate_rows.push(MatrixAggregateValue {
-            name: "Fleet CPU Total".to_string(),
-            value: Box::new(cpu_tot.to_string()),
-        });
-        aggregate_rows.push(MatrixAggregateValue {
-            name: "Fleet Memory Total".to_string(),
-            value: Box::new(mem_tot.to_string()),
-        });
+        let aggregate_rows: Vec<MatrixAggregateValue> = vec![
+            MatrixAggregateValue {
+                name: "Fleet CPU Total".to_string(),
+                value: cpu_tot.into(),
+                function: Some(MatrixAggregateFn::SumElement("Spec".to_string(), 0)),
+            },
+            MatrixAggregateValue {
+                name: "Fleet Memory Total".to_string(),
+                value: mem_tot.into(),
+                function: Some(MatrixAggregateFn::SumElement("Spec".to_string(), 1)),
+            },
+        ];
 
         // Return the completed matrix.
 
@@ -120,7 +153,7 @@ impl EC2Command {
             header: Some(vec!["Instance Inventory".to_string()]),
             rows: Some(main_rows),
             aggregate_rows: Some(aggregate_rows),
-            notes: None,
+            notes: if self.notes.is_empty() { None } else { Some(self.notes.clone()) },
             first_rows_header: true,
         }
     }
@@ -137,15 +170,20 @@ impl Command for EC2Command {
 
         self.textutil.notify_comms(Some("getting instances".to_string()));
         // Get all EC2 instances and run them through Tabled for output
-        match self.handler.ec2_get_all().await {
+        let filters = match &options.subcommand {
+            SubCommands::EC2 { filters, hide_terminated, .. } =>
+                InstanceFilters { hide_terminated: *hide_terminated, ..filters.clone() },
+            _ => InstanceFilters::default(),
+        };
+        match self.handler.ec2_get_all(&filters).await {
             Ok(instances) => {
-                if instances.len() == 0 {
+                if instances.is_empty() {
                     self.textutil.txt_line_output("No instances found.\n".to_string());
                 } else {
                     // Convert the AWS instances to our own type
                     self.textutil.notify_working();
-                    self.instances = to_ec2instances(instances, options.wide, &mut self.handler,
-                                                     &self.instance_filter).await;
+                    (self.instances, self.notes) = to_ec2instances(instances, options.wide, &mut self.handler,
+                                                                   &self.instance_filter, options.concurrency as usize).await;
                     self.instances.sort_by_key(|i| i.get_name());
                     self.textutil.notify_clear();
                 }
@@ -168,45 +206,178 @@ impl Command for EC2Command {
 
 
 /// Convert a vector of AWS SDK EC2 instances into a vector of
-/// Tabled (printable) instances.  If the `wide` option is in force,
-/// additional API calls are made to fill out the enhanced fields.
-async fn to_ec2instances(instances: Vec<Instance>, extended: bool, handler: &mut AWSHandler, filter: &Option<Vec<String>>) -> Vec<EC2Instance> {
-    let mut vec: Vec<EC2Instance> = Vec::new();
-
-    for instance in instances {
-        // Only gather Wide data if wide is enabled.  Otherwise it will waste time unnecessarily.
-
-        let mut ssm = None;
-        let mut az = None;
-        let mut instance_type = None;
-        let mut spec = None;
-
-        if extended {
-            ssm = if handler.instance_can_ssm(&instance).await {
-                Some(true)
-            } else {
-                Some(false)
-            };
+/// Tabled (printable) instances.  Instances not in the filter (if any) are dropped first.  If
+/// the `wide` option is in force, additional API calls are made to fill out the enhanced
+/// fields: each distinct instance profile and type is looked up once, with up to `concurrency`
+/// lookups at a time.  If any of those calls fail, the affected fields are marked unknown and
+/// the reasons are returned as notes.
+async fn to_ec2instances(instances: Vec<Instance>, extended: bool, handler: &mut AWSHandler,
+                         filter: &Option<Vec<String>>, concurrency: usize) -> (Vec<EC2Instance>, Vec<String>) {
+    let instances: Vec<Instance> = instances.into_iter()
+        .filter(|instance| match (filter, instance.instance_id()) {
+            (None, _) => true,
+            (Some(filter), Some(id)) => filter.iter().any(|f| f == id),
+            (Some(_), None) => false,
+        })
+        .collect();
+
+    let mut notes: Vec<String> = Vec::new();
+
+    // Only gather Wide data if wide is enabled.  Otherwise it will waste time unnecessarily.
+    if !extended {
+        let vec = instances.into_iter()
+            .map(|instance| EC2Instance { instance, ssm: None, az: None, instance_type: None, spec: None })
+            .collect();
+        return (vec, notes);
+    }
 
-            az = Some(instance.placement().unwrap().availability_zone().unwrap().to_string());
-            instance_type = Some(instance.instance_type().unwrap().as_str().to_string());
+    // SSM capability depends only on the instance profile.
+    let profile_arns: Vec<String> = unique(instances.iter()
+        .filter_map(|i| i.iam_instance_profile().and_then(|p| p.arn()))
+        .map(|arn| arn.to_string()));
+
+    let mut ssm_by_profile: HashMap<String, Lookup<bool>> = HashMap::new();
+    for (arn, result) in handler.instance_profiles_can_ssm(&profile_arns, concurrency).await {
+        let lookup = match result {
+            Ok(can_ssm) => Lookup::Known(can_ssm),
+            Err(e) => {
+                add_note(&mut notes, format!("SSM status unknown: {}", e));
+                Lookup::Unknown
+            }
+        };
+        ssm_by_profile.insert(arn, lookup);
+    }
 
-            let k = instance.instance_type().unwrap().as_str();
-            spec = handler.get_instance_spec(k).await;
-        }
+    // Specs depend only on the instance type.
+    let instance_types: Vec<String> = unique(instances.iter()
+        .filter_map(|i| i.instance_type())
+        .map(|t| t.as_str().to_string()));
+
+    let mut spec_by_type: HashMap<String, Lookup<InstanceSpec>> = HashMap::new();
+    for instance_type in instance_types {
+        let lookup = match handler.get_instance_spec(&instance_type).await {
+            Ok(Some(spec)) => Lookup::Known(spec),
+            Ok(None) => {
+                add_note(&mut notes, format!("Spec unknown: instance type {} isn't described by AWS.", instance_type));
+                Lookup::Unknown
+            }
+            Err(e) => {
+                add_note(&mut notes, format!("Spec unknown: {}", e));
+                Lookup::Unknown
+            }
+        };
+        spec_by_type.insert(instance_type, lookup);
+    }
+
+    let vec = instances.into_iter()
+        .map(|instance| {
+            // Instances without a profile can't use SSM.
+            let ssm = match instance.iam_instance_profile().and_then(|p| p.arn()) {
+                Some(arn) => ssm_by_profile.get(arn).copied().unwrap_or(Lookup::Unknown),
+                None => Lookup::Known(false),
+            };
+            let az = instance.placement()
+                .and_then(|p| p.availability_zone())
+                .map(|az| az.to_string());
+            let instance_type = instance.instance_type().map(|t| t.as_str().to_string());
+            let spec = instance_type.as_ref().and_then(|t| spec_by_type.get(t).copied());
 
-        if filter.is_none() ||
-            (filter.is_some() && filter.as_ref().unwrap().contains(&instance.instance_id.as_ref().unwrap())) {
-            vec.push(EC2Instance {
+            EC2Instance {
                 instance,
                 // Extended types
-                ssm,
+                ssm: Some(ssm),
                 az,
                 instance_type,
                 spec,
-            });
+            }
+        })
+        .collect();
+
+    (vec, notes)
+}
+
+/// The distinct values of an iterator, in order of first appearance.
+fn unique(values: impl Iterator<Item = String>) -> Vec<String> {
+    let mut seen: HashSet<String> = HashSet::new();
+    values.filter(|v| seen.insert(v.clone())).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::backends::t_fixture_builder::{self, instance_profile_arn, FixtureBuilder};
+    use crate::backends::t_fixture_set::InstanceFixture;
+
+    /// An instance with a Name and a team tag.
+    fn instance(id: &str, name: &str, instance_type: &str, state: &str) -> InstanceFixture {
+        InstanceFixture {
+            private_ip: Some("10.0.0.1".to_string()),
+            tags: BTreeMap::from([("Name".to_string(), name.to_string()), ("team".to_string(), "web".to_string())]),
+            ..t_fixture_builder::instance(id, instance_type, state)
         }
     }
 
-    vec
+    /// Two web servers which can use SSM, a database of a type AWS doesn't describe, and a
+    /// terminated instance.
+    fn fixtures() -> FixtureBuilder {
+        let web = |id: &str, name: &str| InstanceFixture {
+            public_ip: Some("203.0.113.10".to_string()),
+            instance_profile_arn: Some(instance_profile_arn("web")),
+            ..instance(id, name, "m5.large", "running")
+        };
+
+        FixtureBuilder::new()
+            .instance(web("i-02", "web-2"))
+            .instance(instance("i-03", "db", "x9.huge", "stopped"))
+            .instance(web("i-01", "web-1"))
+            .instance(instance("i-04", "old", "m5.large", "terminated"))
+            .spec("m5.large", 2, 8)
+            .instance_profile("web", &["AmazonSSMManagedInstanceCore"])
+    }
+
+    async fn matrix(args: &[&str]) -> Matrix {
+        let (mut options, handler) = fixtures().handler(args);
+        let mut command = EC2Command::with_handler(&options, handler);
+        command.run(&mut options).await.unwrap();
+        command.generate_matrix()
+    }
+
+    /// A column's cells, below the header.
+    fn column(matrix: &Matrix, name: &str) -> Vec<Option<MatrixCell>> {
+        let rows = matrix.rows.as_ref().unwrap();
+        let index = rows[0].iter().position(|h| h.as_ref().is_some_and(|h| h.to_string() == name)).unwrap();
+        rows[1..].iter().map(|row| row[index].clone()).collect()
+    }
+
+    fn strings(cells: &[&str]) -> Vec<Option<MatrixCell>> {
+        cells.iter().map(|cell| Some((*cell).into())).collect()
+    }
+
+    #[tokio::test]
+    async fn lists_instances_by_name() {
+        let matrix = matrix(&["jaws", "ec2", "--hide-terminated"]).await;
+
+        assert_eq!(matrix.rows.as_ref().unwrap()[0].len(), 6);
+        assert_eq!(column(&matrix, "Name"), strings(&["db", "web-1", "web-2"]));
+        assert_eq!(column(&matrix, "Status"), strings(&["stopped", "running", "running"]));
+        assert_eq!(column(&matrix, "Public IP"), vec![None, Some("203.0.113.10".into()), Some("203.0.113.10".into())]);
+    }
+
+    #[tokio::test]
+    async fn wide_adds_ssm_and_specs_with_totals() {
+        let matrix = matrix(&["jaws", "-w", "ec2", "--state", "running,stopped", "--tag-columns", "team,Type"]).await;
+
+        let header: Vec<String> = matrix.rows.as_ref().unwrap()[0].iter().flatten().map(|h| h.to_string()).collect();
+        assert_eq!(header[6..], ["SSM", "AZ", "Type", "Spec", "team", "Tag:Type"]);
+        assert_eq!(column(&matrix, "SSM"), vec![Some(false.into()), Some(true.into()), Some(true.into())]);
+        assert_eq!(column(&matrix, "Spec")[0], Some(MatrixCell::Unknown));
+        assert_eq!(column(&matrix, "team"), strings(&["web", "web", "web"]));
+        assert_eq!(column(&matrix, "Tag:Type"), vec![None, None, None]);
+        assert_eq!(matrix.notes, Some(vec!["Spec unknown: instance type x9.huge isn't described by AWS.".to_string()]));
+
+        let totals: Vec<MatrixCell> = matrix.aggregate_rows.unwrap().into_iter().map(|total| total.value).collect();
+        assert_eq!(totals, [MatrixCell::Integer(4), MatrixCell::Integer(16)]);
+    }
 }
@@ -0,0 +1,327 @@
+use std::collections::BTreeMap;
+use std::io::{stderr, stdin, IsTerminal};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use aws_sdk_ec2::types::Instance;
+
+use crate::e_instance_action::InstanceAction;
+use crate::errors::jaws_error::{JawsError, JawsErrorKind};
+use crate::matrix_handlers::e_matrix_cell::MatrixCell;
+use crate::matrix_handlers::t_matrix_filter::glob_match;
+use crate::matrix_handlers::t_matrix_output::{add_note, Matrix, MatrixFooter, MatrixHeader, MatrixOutput, MatrixRowT,
+                                              MatrixRowsT};
+use crate::matrix_handlers::t_tabular_output::TabularOutput;
+use crate::t_aws_handler::AWSHandler;
+use crate::t_command::Command;
+use crate::t_ec2_instance::EC2Instance;
+use crate::t_instance_filters::InstanceFilters;
+use crate::textutils::{confirm, Textutil};
+use crate::{LifecycleArgs, Options, SubCommands};
+
+/// How often to check on instances while waiting for them to reach their new state.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Start, stop, reboot, terminate or hibernate instances (`jaws ec2 <action>`).  The
+/// instances are chosen by ID, by Name tag or by the same filters as the `ec2` listing, and
+/// shown for confirmation before anything is done.  Unless told not to, the command then
+/// waits for them to reach their new state, and reports the states they end up in.
+pub struct Ec2LifecycleCommand {
+    handler: AWSHandler,
+    textutil: Textutil,
+    output: Option<MatrixOutput>,
+}
+
+impl Ec2LifecycleCommand {
+    pub async fn new(options: &Options) -> Result<Self, JawsError> {
+        Ok(Self {
+            handler: AWSHandler::new(options).await?,
+            textutil: Textutil::new(options),
+            output: None,
+        })
+    }
+
+    /// The live instances named by the targets and matching the filters, ordered by name.
+    /// Every target must match at least one instance.  Terminated instances are left out, as
+    /// nothing more can be done to them.
+    async fn find_instances(&self, action: InstanceAction, args: &LifecycleArgs) -> Result<Vec<EC2Instance>, JawsError> {
+        let filtered = !args.filters.to_sdk().is_empty();
+
+        // The targets are looked up apart from the filters, and the two intersected: AWS would
+        // take a Name target and a --name filter as alternatives, where both must match.
+        let targeted = if args.targets.is_empty() {
+            None
+        } else {
+            let (ids, names): (Vec<String>, Vec<String>) = args.targets.iter().cloned()
+                .partition(|target| is_instance_id(target));
+            let mut queries: Vec<InstanceFilters> = Vec::new();
+            if !ids.is_empty() {
+                queries.push(InstanceFilters { ids, ..Default::default() });
+            }
+            if !names.is_empty() {
+                queries.push(InstanceFilters { names, ..Default::default() });
+            }
+            Some(self.get_instances(&queries).await?)
+        };
+        let matching = if filtered {
+            Some(self.get_instances(std::slice::from_ref(&args.filters)).await?)
+        } else {
+            None
+        };
+
+        let found: BTreeMap<String, Instance> = match (targeted, matching) {
+            (Some(targeted), Some(matching)) => targeted.into_iter()
+                .filter(|(id, _)| matching.contains_key(id))
+                .collect(),
+            (Some(found), None) | (None, Some(found)) => found,
+            (None, None) => return Err(JawsError::with_kind(JawsErrorKind::Usage,
+                format!("Name the instances to {}, by ID or Name tag, or with filters such as --tag.", action))),
+        };
+
+        let mut instances: Vec<EC2Instance> = found.into_values()
+            .filter(|instance| state_of(instance) != "terminated")
+            .map(|instance| EC2Instance { instance, ssm: None, az: None, instance_type: None, spec: None })
+            .collect();
+        instances.sort_by_key(|i| i.get_name());
+
+        let unmatched: Vec<&String> = args.targets.iter()
+            .filter(|target| !instances.iter().any(|i| matches_target(i, target)))
+            .collect();
+        if !unmatched.is_empty() {
+            return Err(JawsError::with_kind(JawsErrorKind::NotFound,
+                format!("No live instance{} matches {}.",
+                        if filtered { " matching the filters" } else { "" },
+                        unmatched.iter()
+                            .map(|target| format!("'{}'", target))
+                            .collect::<Vec<_>>()
+                            .join(", "))));
+        }
+        if instances.is_empty() {
+            return Err(JawsError::with_kind(JawsErrorKind::NotFound,
+                "No live instances match the filters.".to_string()));
+        }
+        Ok(instances)
+    }
+
+    /// The instances matching any of the queries, by ID.
+    async fn get_instances(&self, queries: &[InstanceFilters]) -> Result<BTreeMap<String, Instance>, JawsError> {
+        let mut found: BTreeMap<String, Instance> = BTreeMap::new();
+        for query in queries {
+            for instance in self.handler.ec2_get_all(query).await? {
+                if let Some(id) = instance.instance_id() {
+                    found.insert(id.to_string(), instance);
+                }
+            }
+        }
+        Ok(found)
+    }
+
+    /// Show the instances on the terminal and ask whether to go ahead.  Without a terminal to
+    /// ask on, `--yes` is needed.
+    fn confirm(&self, options: &Options, action: InstanceAction, instances: &[EC2Instance]) -> Result<bool, JawsError> {
+        if !stdin().is_terminal() {
+            return Err(JawsError::with_kind(JawsErrorKind::Usage,
+                format!("Can't ask for confirmation, as input isn't a terminal.\n\nUse --yes to {} the instances without asking.",
+                        action)));
+        }
+
+        let tabular = TabularOutput {
+            styled: stderr().is_terminal(),
+            profile: options.active_profile(),
+            table_style: options.table_style.unwrap_or_default(),
+        };
+        let matrix = Matrix {
+            header: Some(vec![format!("Instances to {}", capitalize(&action.to_string()))]),
+            rows: Some(state_rows(instances, None)),
+            aggregate_rows: None,
+            notes: None,
+            first_rows_header: true,
+        };
+
+        self.textutil.notify_clear();
+        tabular.output_matrix(&mut stderr(), &matrix)?;
+        Ok(confirm(&format!("{} {} instance(s)?", capitalize(&action.to_string()), instances.len()))?)
+    }
+
+    /// Wait until every instance is in `target` state (or terminated), or the timeout passes.
+    /// Instances AWS no longer reports aren't waited for; when terminating, they count as
+    /// terminated, since AWS stops reporting instances some time after terminating them.
+    /// Returns the instances as last seen, and whether they all got there.
+    async fn wait_for(&self, ids: &[String], target: &str, timeout: Duration) -> Result<(Vec<Instance>, bool), JawsError> {
+        let filters = InstanceFilters { ids: ids.to_vec(), ..Default::default() };
+        let start = Instant::now();
+
+        loop {
+            let instances = self.handler.ec2_get_all(&filters).await?;
+            let settled = instances.iter()
+                .filter(|i| [target, "terminated"].contains(&state_of(i)))
+                .count();
+            let missing = missing_ids(ids, &instances).len();
+            let settled = if target == "terminated" { settled + missing } else { settled };
+            if settled + missing >= ids.len() || start.elapsed() >= timeout {
+                return Ok((instances, settled == ids.len()));
+            }
+
+            self.textutil.notify_comms(Some(format!("waiting for instances to be {}: {} of {} ({})", target, settled,
+                                                    ids.len(), self.textutil.to_hms(start.elapsed().as_secs()))));
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+}
+
+#[async_trait]
+impl Command for Ec2LifecycleCommand {
+    async fn run(&mut self, options: &mut Options) -> Result<(), JawsError> {
+        let (action, args) = match &options.subcommand {
+            SubCommands::EC2 { action: Some(action), .. } => (action.action(), action.args().clone()),
+            _ => return Err(JawsError::new("The EC2 lifecycle command was run without an action.".to_string())),
+        };
+
+        self.textutil.notify_comms(Some("checking caller ID".to_string()));
+        self.handler.sts_get_caller_identity().await?;
+
+        self.textutil.notify_comms(Some("finding instances".to_string()));
+        let instances = self.find_instances(action, &args).await?;
+        let ids: Vec<String> = instances.iter()
+            .filter_map(|i| i.instance.instance_id().map(|id| id.to_string()))
+            .collect();
+
+        if args.dry_run {
+            self.textutil.notify_comms(Some(format!("checking {} would succeed", action)));
+            self.handler.ec2_change_instance_state(action, &ids, true).await?;
+            self.textutil.notify_clear();
+
+            let mut notes: Vec<String> = Vec::new();
+            add_note(&mut notes, "AWS says the request would succeed; nothing was changed.".to_string());
+            self.output = Some(lifecycle_output(Matrix {
+                header: Some(vec![format!("Instances to {} (Dry Run)", capitalize(&action.to_string()))]),
+                rows: Some(state_rows(&instances, None)),
+                aggregate_rows: None,
+                notes: Some(notes),
+                first_rows_header: true,
+            }));
+            return Ok(());
+        }
+
+        if !args.yes && !self.confirm(options, action, &instances)? {
+            self.textutil.notify("Nothing was done.\n".to_string());
+            return Ok(());
+        }
+
+        self.textutil.notify_comms(Some(format!("requesting {}", action)));
+        self.handler.ec2_change_instance_state(action, &ids, false).await?;
+
+        let mut notes: Vec<String> = Vec::new();
+        let after = match action.target_state().filter(|_| !args.no_wait) {
+            Some(target) => {
+                let (after, settled) = self.wait_for(&ids, target, Duration::from_secs(args.timeout)).await?;
+                if !settled && missing_ids(&ids, &after).is_empty() {
+                    add_note(&mut notes, format!("Gave up after {} waiting for the instances to be {}.",
+                                                 self.textutil.to_hms(args.timeout), target));
+                }
+                after
+            }
+            None => {
+                if action == InstanceAction::Reboot {
+                    add_note(&mut notes, "AWS doesn't report when a reboot has finished.".to_string());
+                }
+                self.handler.ec2_get_all(&InstanceFilters { ids: ids.clone(), ..Default::default() }).await?
+            }
+        };
+        self.textutil.notify_clear();
+
+        let missing = missing_ids(&ids, &after);
+        if !missing.is_empty() {
+            add_note(&mut notes, format!("AWS no longer reports {}, so {} state after is unknown.",
+                                         missing.join(", "), if missing.len() == 1 { "its" } else { "their" }));
+        }
+
+        self.output = Some(lifecycle_output(Matrix {
+            header: Some(vec![format!("Instances {}", action.done())]),
+            rows: Some(state_rows(&instances, Some(&after))),
+            aggregate_rows: None,
+            notes: if notes.is_empty() { None } else { Some(notes) },
+            first_rows_header: true,
+        }));
+        Ok(())
+    }
+
+    fn get_matrix_output(&mut self) -> Option<MatrixOutput> {
+        self.output.take()
+    }
+}
+
+/// A row for each instance.  With the instances as they are after the action, there are
+/// Before and After state columns; otherwise there's only the State.
+fn state_rows(instances: &[EC2Instance], after: Option<&[Instance]>) -> MatrixRowsT {
+    let mut header: MatrixRowT = vec![
+        Some("Instance ID".into()),
+        Some("Name".into()),
+        Some("Type".into()),
+        Some("AZ".into()),
+    ];
+    match after {
+        Some(_) => header.extend([Some("Before".into()), Some("After".into())]),
+        None => header.push(Some("State".into())),
+    }
+
+    let mut rows: MatrixRowsT = vec![header];
+    for instance in instances {
+        let id = instance.instance.instance_id();
+        let mut row: MatrixRowT = vec![
+            id.map(MatrixCell::from),
+            Some(instance.get_name().into()),
+            instance.instance.instance_type().map(|t| t.as_str().into()),
+            instance.instance.placement().and_then(|p| p.availability_zone()).map(MatrixCell::from),
+            Some(state_of(&instance.instance).into()),
+        ];
+        if let Some(after) = after {
+            row.push(after.iter()
+                .find(|i| i.instance_id() == id)
+                .map(|i| state_of(i).into()));
+        }
+        rows.push(row);
+    }
+    rows
+}
+
+fn lifecycle_output(matrix: Matrix) -> MatrixOutput {
+    MatrixOutput {
+        matrix_header: Some(MatrixHeader { title: Some("EC2".to_string()), output_program_header: true }),
+        matrix_footer: Some(MatrixFooter { footer: None, output_program_footer: true }),
+        matrices: vec![matrix],
+    }
+}
+
+/// The IDs of the instances which aren't among those AWS reported.
+fn missing_ids<'a>(ids: &'a [String], instances: &[Instance]) -> Vec<&'a str> {
+    ids.iter()
+        .filter(|id| !instances.iter().any(|i| i.instance_id() == Some(id.as_str())))
+        .map(|id| id.as_str())
+        .collect()
+}
+
+fn state_of(instance: &Instance) -> &str {
+    instance.state().and_then(|s| s.name()).map(|n| n.as_str()).unwrap_or("unknown")
+}
+
+/// Whether a target is an instance ID rather than a Name.
+fn is_instance_id(target: &str) -> bool {
+    target.strip_prefix("i-").is_some_and(|hex| !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+fn matches_target(instance: &EC2Instance, target: &str) -> bool {
+    if is_instance_id(target) {
+        instance.instance.instance_id() == Some(target)
+    } else {
+        instance.find_tag_value("Name").is_some_and(|name| glob_match(target, &name))
+    }
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
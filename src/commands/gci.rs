@@ -16,7 +16,7 @@ impl Command for GCICommand
         let textutil = Textutil::new(options);
 
         textutil.notify_comms(None);
-        let handler: AWSHandler = AWSHandler::new(options).await;
+        let handler: AWSHandler = AWSHandler::new(options).await?;
 
         let id = handler.sts_get_caller_identity();
         let identity_result = id.await;
@@ -28,6 +28,9 @@ impl Command for GCICommand
                 println!("ARN:        {}", identity.arn().unwrap());
                 println!("Account:    {}", identity.account().unwrap());
                 println!("User:       {}", identity.user_id().unwrap());
+                if let Some(profile) = options.active_profile() {
+                    println!("Profile:    {}", profile);
+                }
                 Ok(())
             }
             Err(e) => {
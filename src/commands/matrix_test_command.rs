@@ -1,6 +1,7 @@
-use std::fmt::Display;
+use chrono::{TimeDelta, TimeZone, Utc};
 use crate::errors::jaws_error::JawsError;
-use crate::matrix_handlers::t_matrix_output::{Matrix, MatrixAggregateValue, MatrixFooter, MatrixHeader, MatrixOutput};
+use crate::matrix_handlers::e_matrix_cell::MatrixCell;
+use crate::matrix_handlers::t_matrix_output::{Matrix, MatrixAggregateFn, MatrixAggregateValue, MatrixFooter, MatrixHeader, MatrixOutput};
 use crate::t_command::Command;
 use crate::Options;
 use async_trait::async_trait;
@@ -23,48 +24,53 @@ impl MatrixTestCommand {
         let matrix = Matrix {
             header: Some(vec![String::from("The Matrix Title")]),
 
-            // pub rows: Vec<Vec<Option<Box<dyn Display>>>>,
-
-            rows: Some::<Vec<Vec<Option<Box<dyn Display>>>>> (vec![
+            rows: Some(vec![
                 vec![
-                    Some(Box::new(String::from("Column A"))),
-                    Some(Box::new(String::from("Column B"))),
-                    Some(Box::new(String::from("Column C"))),
+                    Some("Column A".into()),
+                    Some("Column B".into()),
+                    Some("Column C".into()),
+                    Some("Column D".into()),
                 ],
                 vec![
-                    Some(Box::new(String::from("Foo"))),
+                    Some("Foo".into()),
                     None,
-                    Some(Box::new(String::from("32"))),
+                    Some(32.into()),
+                    Some(MatrixCell::usd(1234.5)),
                 ],
                 vec![
-                    Some(Box::new(String::from("Bar"))),
-                    Some(Box::new(String::from("Quux"))),
-                    Some(Box::new(String::from("Slartibartfast"))),
+                    Some(MatrixCell::List(vec![4.into(), 16.into()])),
+                    Some(true.into()),
+                    Some(2.5.into()),
+                    Some(MatrixCell::usd(0.25)),
                 ],
                 vec![
                     None,
-                    Some(Box::new(String::from("Arthur"))),
-                    Some(Box::new(String::from("Trillian"))),
+                    Some("Arthur".into()),
+                    Some(MatrixCell::Duration(TimeDelta::hours(42))),
+                    Some(Utc.with_ymd_and_hms(1979, 10, 12, 0, 0, 0).unwrap().into()),
                 ],
             ]),
-            //    pub aggregate_rows: Vec<Vec<MatrixAggregateValue>>,
             aggregate_rows: Some(
                 vec![
                     MatrixAggregateValue {
                         name: String::from("Total"),
-                        value: Box::new(2)
+                        value: 3.into(),
+                        function: Some(MatrixAggregateFn::Count),
                     },
                     MatrixAggregateValue {
                         name: String::from("Average"),
-                        value: Box::new(std::f64::consts::PI)
+                        value: std::f64::consts::PI.into(),
+                        function: None,
                     },
                     MatrixAggregateValue {
                         name: String::from("95th Percentile"),
-                        value: Box::new(78)
+                        value: 78.into(),
+                        function: None,
                     },
                     MatrixAggregateValue {
                         name: String::from("Fred"),
-                        value: Box::new("Jim, Sheila")
+                        value: "Jim, Sheila".into(),
+                        function: None,
                     },
                 ]
             ),
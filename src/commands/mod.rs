@@ -1,6 +1,8 @@
 pub mod gci;
 pub mod ec2;
+pub mod ec2_lifecycle;
 pub mod ssm;
 pub mod res;
 pub mod matrix_test_command;
+pub mod cache;
 
@@ -2,23 +2,32 @@ use async_trait::async_trait;
 use aws_sdk_ec2::types::{Instance, RecurringCharge, RecurringChargeFrequency, ReservedInstances};
 use aws_smithy_types_convert::date_time::DateTimeExt;
 use chrono::{DateTime, Utc};
-use rust_decimal::prelude::*;
-use rusty_money::{iso, Money, Round};
-use std::fmt::Display;
+
+use std::collections::HashSet;
 
 use crate::commands::ec2::EC2Command;
 use crate::errors::jaws_error::JawsError;
+use crate::matrix_handlers::e_matrix_cell::MatrixCell;
 use crate::matrix_handlers::t_matrix_output::{
-    Matrix, MatrixAggregateValue, MatrixFooter, MatrixHeader, MatrixOutput, MatrixRowT, MatrixRowsT,
+    add_note, Matrix, MatrixAggregateFn, MatrixAggregateValue, MatrixFooter, MatrixHeader, MatrixOutput, MatrixRowT, MatrixRowsT,
 };
 use crate::t_aws_handler::AWSHandler;
 use crate::t_command::Command;
+use crate::t_instance_filters::InstanceFilters;
 use crate::textutils::Textutil;
 use crate::{Options, SubCommands};
 
 const SECONDS_PER_YEAR: i32 = 60 * 60 * 24 * 365;
 const HOURS_PER_YEAR: i32 = 24 * 365;
 
+/// Which of an account's running instances are covered by reservations, and the
+/// reservations left unused (with only their unused counts).
+struct Coverage {
+    covered: Vec<String>,
+    uncovered: Vec<String>,
+    unused: Vec<ReservedInstances>,
+}
+
 pub struct ResCommand {
     model: Option<CalculationModel>,
     unused_model: Option<CalculationModel>,
@@ -27,8 +36,6 @@ pub struct ResCommand {
     wide: bool,
 }
 
-unsafe impl Send for ResCommand {}
-
 impl ResCommand {
     pub fn new() -> Self {
         Self {
@@ -40,71 +47,75 @@ impl ResCommand {
         }
     }
 
-    fn get_reservations_matrix(&self) -> Matrix {
-        let mut main_rows: MatrixRowsT = Vec::new();
-        let mut header: Vec<Option<Box<dyn Display>>> = Vec::new();
-        header.push(Some(Box::new("Type".to_string())));
-        header.push(Some(Box::new("#".to_string())));
-        header.push(Some(Box::new("AZ".to_string())));
-        header.push(Some(Box::new("Expiry".to_string())));
-        header.push(Some(Box::new("Days".to_string())));
-        header.push(Some(Box::new("Term Yrs".to_string())));
-        header.push(Some(Box::new("Model".to_string())));
-        header.push(Some(Box::new("$ Res/Hr".to_string())));
-        header.push(Some(Box::new("$ Res/Fixed".to_string())));
-        header.push(Some(Box::new("$ Res/Year".to_string())));
-        header.push(Some(Box::new("$ ODM/Hr".to_string())));
-        header.push(Some(Box::new("$ ODM/Year".to_string())));
-        header.push(Some(Box::new("$ Saving/Year".to_string())));
-
-        main_rows.push(header);
+    fn get_reservations_matrix(&self, model: &CalculationModel) -> Matrix {
+        let header: MatrixRowT = vec![
+            Some("Type".into()),
+            Some("#".into()),
+            Some("AZ".into()),
+            Some("Expiry".into()),
+            Some("Days".into()),
+            Some("Term Yrs".into()),
+            Some("Model".into()),
+            Some("$ Res/Hr".into()),
+            Some("$ Res/Fixed".into()),
+            Some("$ Res/Year".into()),
+            Some("$ ODM/Hr".into()),
+            Some("$ ODM/Year".into()),
+            Some("$ Saving/Year".into()),
+        ];
+
+        let mut main_rows: MatrixRowsT = vec![header];
 
         let mut total_res_count: i32 = 0;
         let mut total_res_expenditure_year: f32 = 0.0;
         let mut total_res_saving: f32 = 0.0;
 
-        for element in &self.model.as_ref().unwrap().elements {
-            let mut row: MatrixRowT = Vec::new();
-            row.push(Some(Box::new(element.name.clone())));
-            row.push(Some(Box::new(element.qty.to_string())));
-            row.push(Some(Box::new(element.az.clone())));
-            row.push(Some(Box::new(element.expiry.to_string())));
-            row.push(Some(Box::new(element.days_remaining.to_string())));
-            row.push(Some(Box::new(element.term_years.to_string())));
-            row.push(Some(Box::new(element.res_model.to_string())));
-            row.push(Some(Box::new(format_money(element.res_recurring))));
-            row.push(Some(Box::new(format_money(element.res_fixed))));
-            row.push(Some(Box::new(format_money(element.res_yearly))));
-            row.push(Some(Box::new(format_money(element.odm_rate))));
-            row.push(Some(Box::new(format_money(element.odm_yearly))));
-            row.push(Some(Box::new(format_money(element.saving_yearly))));
+        for element in &model.elements {
+            let row: MatrixRowT = vec![
+                Some(or_unknown(element.name.clone())),
+                Some(or_unknown(element.qty)),
+                Some(element.az.clone().into()),
+                Some(or_unknown(element.expiry)),
+                Some(or_unknown(element.days_remaining)),
+                Some(or_unknown(element.term_years)),
+                Some(or_unknown(element.res_model.clone())),
+                Some(usd_or_unknown(element.res_recurring)),
+                Some(usd_or_unknown(element.res_fixed)),
+                Some(usd_or_unknown(element.res_yearly)),
+                Some(usd_or_unknown(element.odm_rate)),
+                Some(usd_or_unknown(element.odm_yearly)),
+                Some(usd_or_unknown(element.saving_yearly)),
+            ];
             main_rows.push(row);
 
-            total_res_count += element.qty;
-            total_res_expenditure_year += element.res_yearly;
-            total_res_saving += element.saving_yearly;
+            total_res_count += element.qty.unwrap_or_default();
+            total_res_expenditure_year += element.res_yearly.unwrap_or_default();
+            total_res_saving += element.saving_yearly.unwrap_or_default();
         }
 
         let aggregate_rows = Some(vec![
             MatrixAggregateValue {
                 name: "Total Reservations".to_string(),
-                value: Box::new(total_res_count),
+                value: total_res_count.into(),
+                function: Some(MatrixAggregateFn::Sum("#".to_string())),
             },
             MatrixAggregateValue {
                 name: "Total Yearly Spend".to_string(),
-                value: Box::new(format_money(total_res_expenditure_year)),
+                value: MatrixCell::usd(total_res_expenditure_year),
+                function: Some(MatrixAggregateFn::Sum("$ Res/Year".to_string())),
             },
             MatrixAggregateValue {
                 name: "Total Yearly Saving".to_string(),
-                value: Box::new(format_money(total_res_saving)),
+                value: MatrixCell::usd(total_res_saving),
+                function: Some(MatrixAggregateFn::Sum("$ Saving/Year".to_string())),
             },
         ]);
 
         Matrix {
             header: Some(vec!["Active Reservations".to_string()]),
             rows: Some(main_rows),
-            aggregate_rows: aggregate_rows, // Some(aggregate_rows),
-            notes: None,
+            aggregate_rows, // Some(aggregate_rows),
+            notes: if model.notes.is_empty() { None } else { Some(model.notes.clone()) },
             first_rows_header: true,
         }
     }
@@ -113,7 +124,7 @@ impl ResCommand {
 #[async_trait]
 impl Command for ResCommand {
     async fn run(&mut self, options: &mut Options) -> Result<(), JawsError> {
-        let mut handler = AWSHandler::new(options).await;
+        let handler = AWSHandler::new(options).await?;
 
         let textutil = Textutil::new(options);
 
@@ -125,58 +136,31 @@ impl Command for ResCommand {
 
         // Check we got a a good result (and return early if not)
 
-        let mut reservations = match reservations_result {
+        let reservations = match reservations_result {
             Ok(reservations) => reservations,
             Err(err) => return Err(err),
         };
 
         // Otherwise we have a good list of reservations.
 
-        if reservations.len() == 0 {
+        if reservations.is_empty() {
             textutil.notify_clear();
-            println!("No active reservations found.");
+            textutil.notify("No active reservations found.\n".to_string());
             return Ok(());
         }
 
-        // Create a model now which will support output in tabular form, the same as Jaws-1.
-
-        textutil.notify_working();
-        self.model = Some(calculate_model(&reservations, &mut handler).await);
-        textutil.notify_clear();
-
-        // .. and output it
-        // textutil.report_title(format!("EC2 Reservations ({})", model.elements.len()));
-        // dump_model_tabular(&model);
-
         // If --show-unused is present, get all EC2 instances and thin out the reservations.
-        if let SubCommands::RES { show_unused: true } = options.subcommand {
-            self.wide = true;
-            let mut instance_result = handler.ec2_get_all().await?;
-
-            // remove Terminated instances for this command.
-            instance_result.retain(|x| x.state().unwrap().name().unwrap().as_str() == "running");
-            let (covered_instances, uncovered_instances) =
-                thin_reservations(&instance_result, &mut reservations);
-
-            // Calculate and dump the unused reservations  (or a "none" string if there aren't any).
-            self.unused_model = Some(calculate_model(&reservations, &mut handler).await);
-
-            options.wide = true;
-
-            let mut ec2_command = EC2Command::new(&options).await;
-
-            ec2_command
-                .run_with_filter(uncovered_instances, options)
-                .await;
-            self.uncovered_instances_matrix = ec2_command.get_matrix_output();
-
-            ec2_command
-                .run_with_filter(covered_instances, options)
-                .await;
-            self.covered_instances_matrix = ec2_command.get_matrix_output();
-        }
+        let coverage = if show_unused(options) {
+            // Only running instances can be covered.
+            let instances = handler.ec2_get_all(&InstanceFilters::running()).await?;
+            let mut unused = reservations.clone();
+            let (covered, uncovered) = thin_reservations(&instances, &mut unused);
+            Some(Coverage { covered, uncovered, unused })
+        } else {
+            None
+        };
 
-        Ok(())
+        self.report(options, handler, &reservations, coverage).await
     }
 
     fn get_matrix_output(&mut self) -> Option<MatrixOutput> {
@@ -186,7 +170,8 @@ impl Command for ResCommand {
         // and this is drawn from the EC2 command.  This _does_ honour --wide, and will produce
         // matrices of its own.  These must be integrated into `res`'s output.
 
-        let reservations_matrix = self.get_reservations_matrix();
+        // There's nothing to output if no reservations were found.
+        let reservations_matrix = self.get_reservations_matrix(self.model.as_ref()?);
 
         let mut matrices: Vec<Matrix> = vec![reservations_matrix];
 
@@ -222,13 +207,115 @@ impl Command for ResCommand {
                 footer: None,
                 output_program_footer: true,
             }),
-            matrices: matrices,
+            matrices,
         })
     }
 }
 
+impl ResCommand {
+    /// Work out one account's report from its reservations and, with --show-unused, its
+    /// coverage.
+    async fn report(&mut self, options: &mut Options, mut handler: AWSHandler, reservations: &[ReservedInstances],
+                    coverage: Option<Coverage>) -> Result<(), JawsError> {
+        let textutil = Textutil::new(options);
+
+        // Create a model now which will support output in tabular form, the same as Jaws-1.
+        textutil.notify_working();
+        self.model = Some(calculate_model(reservations, &mut handler).await);
+        textutil.notify_clear();
+
+        if let Some(coverage) = coverage {
+            self.wide = true;
+
+            // Calculate and dump the unused reservations  (or a "none" string if there aren't any).
+            self.unused_model = Some(calculate_model(&coverage.unused, &mut handler).await);
+
+            options.wide = true;
+
+            let mut ec2_command = EC2Command::with_handler(options, handler);
+
+            ec2_command
+                .run_with_filter(coverage.uncovered, options)
+                .await?;
+            self.uncovered_instances_matrix = ec2_command.get_matrix_output();
+
+            ec2_command
+                .run_with_filter(coverage.covered, options)
+                .await?;
+            self.covered_instances_matrix = ec2_command.get_matrix_output();
+        }
+
+        Ok(())
+    }
+}
+
+/// Run `res` in several accounts in one region, pooling their reservations as consolidated
+/// billing does: any account's running instances can use any account's reservations.  Each
+/// account still gets its own report, of its own reservations and instances, for merging.
+/// An account whose data can't be fetched gets its error, and is left out of the pool.
+/// Each account has its own options and handler, and results are in the order of the
+/// accounts.
+pub async fn run_pooled(options: &Options, accounts: Vec<(Options, AWSHandler)>)
+                        -> Vec<Result<Option<MatrixOutput>, JawsError>> {
+    let mut results = Vec::new();
+
+    let mut fetched = Vec::new();
+    for (n, (account_options, handler)) in accounts.into_iter().enumerate() {
+        let data = async {
+            let reservations = handler.reservations_get_live().await?;
+            let instances = if show_unused(options) {
+                handler.ec2_get_all(&InstanceFilters::running()).await?
+            } else {
+                Vec::new()
+            };
+            Ok::<_, JawsError>((reservations, instances))
+        }.await;
+
+        match data {
+            Ok((reservations, instances)) => fetched.push((n, account_options, handler, reservations, instances)),
+            Err(e) => results.push((n, Err(e))),
+        }
+    }
+
+    let mut unused: Vec<ReservedInstances> = fetched.iter().flat_map(|(.., reservations, _)| reservations.clone()).collect();
+    let instances: Vec<Instance> = fetched.iter().flat_map(|(.., instances)| instances.clone()).collect();
+    let (covered, uncovered) = thin_reservations(&instances, &mut unused);
+
+    for (n, mut account_options, handler, reservations, instances) in fetched {
+        let coverage = if show_unused(options) {
+            let ids: HashSet<&str> = instances.iter().filter_map(|i| i.instance_id()).collect();
+            let reservation_ids: HashSet<&str> = reservations.iter().filter_map(|r| r.reserved_instances_id()).collect();
+            Some(Coverage {
+                covered: covered.iter().filter(|id| ids.contains(id.as_str())).cloned().collect(),
+                uncovered: uncovered.iter().filter(|id| ids.contains(id.as_str())).cloned().collect(),
+                unused: unused.iter()
+                    .filter(|r| r.reserved_instances_id().is_some_and(|id| reservation_ids.contains(id)))
+                    .cloned()
+                    .collect(),
+            })
+        } else if reservations.is_empty() {
+            results.push((n, Ok(None)));
+            continue;
+        } else {
+            None
+        };
+
+        let mut command = ResCommand::new();
+        let result = command.report(&mut account_options, handler, &reservations, coverage).await
+            .map(|_| command.get_matrix_output());
+        results.push((n, result));
+    }
+
+    results.sort_by_key(|(n, _)| *n);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+fn show_unused(options: &Options) -> bool {
+    matches!(options.subcommand, SubCommands::RES { show_unused: true, .. })
+}
+
 fn thin_reservations(
-    instances: &Vec<Instance>,
+    instances: &[Instance],
     reservations: &mut Vec<ReservedInstances>,
 ) -> (Vec<String>, Vec<String>) {
     // The idea of this function is to remove single reservations as each instance "consumes"
@@ -238,19 +325,23 @@ fn thin_reservations(
     let mut covered: Vec<String> = Vec::new();
 
     'instances: for instance in instances.iter().by_ref() {
-        // Find a reservation that applies to this instance.
+        // An instance without an ID couldn't be listed anyway.
+        let Some(id) = instance.instance_id() else {
+            continue;
+        };
+
+        // Find a reservation that applies to this instance.  One whose type or count is
+        // unknown can't be matched.
         for reservation in reservations.iter_mut() {
-            if instance.instance_type.as_ref().unwrap()
-                == reservation.instance_type.as_ref().unwrap()
-            {
+            if instance.instance_type().is_some() && instance.instance_type() == reservation.instance_type() {
                 // This reservation applies to this instance.
 
                 // If this reservation has any more instances in it, decrement them. Otherwise,
                 // move on to the next instance.
 
-                if reservation.instance_count.unwrap() > 0 {
-                    reservation.instance_count = Some(reservation.instance_count.unwrap() - 1);
-                    covered.push(instance.instance_id.as_ref().unwrap().clone());
+                if let Some(count @ 1..) = reservation.instance_count {
+                    reservation.instance_count = Some(count - 1);
+                    covered.push(id.to_string());
                     continue 'instances;
                 }
                 // else continue the next reservation.
@@ -258,11 +349,11 @@ fn thin_reservations(
         }
 
         // If we get this far, we exhausted all the reservations - this is an uncovered instance.
-        uncovered.push(instance.instance_id.as_ref().unwrap().clone());
+        uncovered.push(id.to_string());
     }
 
     // Retain instances in the reservations vec with counts > 0
-    reservations.retain(|x| x.instance_count.unwrap() > 0);
+    reservations.retain(|x| x.instance_count.is_some_and(|count| count > 0));
 
     (covered, uncovered)
 }
@@ -273,88 +364,131 @@ struct CalculationModel {
     // Resv. fixed fee, ODM Rate, Yearly ODM, Yearly Actual, Saving
     // Total Yearly ODM, total Actual, total saving.
     elements: Vec<ReservationElement>,
+    // Explanations for any values which couldn't be calculated.
+    notes: Vec<String>,
 }
 
+// Values which couldn't be determined (e.g. because pricing data couldn't be fetched, or AWS
+// left a field out) are None, and are shown as unknown.
 struct ReservationElement {
-    name: String,
-    qty: i32,
+    name: Option<String>,
+    qty: Option<i32>,
     az: String,
-    expiry: DateTime<Utc>,
-    days_remaining: i64,
-    term_years: i64,
-    res_model: String,
-    res_recurring: f32,
-    res_fixed: f32,
-    res_yearly: f32,
-    odm_rate: f32,
-    odm_yearly: f32,
-    saving_yearly: f32,
+    expiry: Option<DateTime<Utc>>,
+    days_remaining: Option<i64>,
+    term_years: Option<i64>,
+    res_model: Option<String>,
+    res_recurring: Option<f32>,
+    res_fixed: Option<f32>,
+    res_yearly: Option<f32>,
+    odm_rate: Option<f32>,
+    odm_yearly: Option<f32>,
+    saving_yearly: Option<f32>,
 }
 
 async fn calculate_model(
-    reservations: &Vec<ReservedInstances>,
+    reservations: &[ReservedInstances],
     handler: &mut AWSHandler,
 ) -> CalculationModel {
     let mut elements = Vec::new();
-
-    let mut total_odm_yearly: f32 = 0.0;
-    let mut total_res_yearly: f32 = 0.0;
+    let mut notes: Vec<String> = Vec::new();
 
     for res in reservations {
         // We need to precompute some values because they are used in calculation further down
         // the struct.
+        let instance_type = res.instance_type();
+        let qty = res.instance_count();
+        let expiry = res.end().and_then(|end| end.to_chrono_utc().ok());
+        let term_years = res.duration().map(|duration| duration / SECONDS_PER_YEAR as i64);
+        let res_model = res.offering_type().map(|offering| offering.to_string());
+        let res_fixed = res.fixed_price();
+
+        let missing: Vec<&str> = [
+            ("instance type", instance_type.is_none()),
+            ("instance count", qty.is_none()),
+            ("end date", expiry.is_none()),
+            ("duration", term_years.is_none()),
+            ("offering type", res_model.is_none()),
+            ("fixed price", res_fixed.is_none()),
+        ].into_iter().filter(|(_, missing)| *missing).map(|(field, _)| field).collect();
+        if !missing.is_empty() {
+            add_note(&mut notes, format!("Reservation {} has no {}; shown as unknown.",
+                                         res.reserved_instances_id().unwrap_or("without an ID"), missing.join(", ")));
+        }
+
+        let res_recurring = match sum_recurring_charges(res.recurring_charges()) {
+            Ok(sum) => Some(sum),
+            Err(e) => {
+                add_note(&mut notes, format!("Reservation costs unknown: {}", e));
+                None
+            }
+        };
+        let odm_rate = match instance_type {
+            Some(instance_type) => match handler.get_odm_rate(instance_type).await {
+                Ok(rate) => Some(rate),
+                Err(e) => {
+                    add_note(&mut notes, format!("On-demand costs unknown: {}", e));
+                    None
+                }
+            },
+            None => None,
+        };
 
-        let res_yearly: f32 = sum_recurring_charges(res.recurring_charges())
-            * (HOURS_PER_YEAR as f32)
-            * res.instance_count.unwrap() as f32;
-        let odm_yearly: f32 = handler.get_odm_rate(res.instance_type().unwrap()).await
-            * (HOURS_PER_YEAR as f32)
-            * res.instance_count.unwrap() as f32;
+        let yearly = |rate: f32, qty: i32| rate * (HOURS_PER_YEAR as f32) * qty as f32;
+        let res_yearly = res_recurring.zip(qty).map(|(rate, qty)| yearly(rate, qty));
+        let odm_yearly = odm_rate.zip(qty).map(|(rate, qty)| yearly(rate, qty));
 
         elements.push(ReservationElement {
-            name: String::from(res.instance_type().unwrap().as_str()),
-            qty: res.instance_count.unwrap(),
-            az: String::from(
-                res.availability_zone()
-                    .unwrap_or("None".to_string().as_str()),
-            ),
-            expiry: res.end().unwrap().to_chrono_utc().unwrap(),
-            days_remaining: days_remaining(res.end().unwrap().to_chrono_utc().unwrap()),
-            term_years: res.duration().unwrap() / SECONDS_PER_YEAR as i64,
-            res_model: res.offering_type().unwrap().to_string(),
-            res_recurring: sum_recurring_charges(res.recurring_charges()),
-            res_fixed: res.fixed_price.unwrap(),
-            res_yearly: res_yearly,
-            odm_rate: handler.get_odm_rate(res.instance_type().unwrap()).await,
-            odm_yearly: odm_yearly,
-            saving_yearly: odm_yearly - res_yearly,
+            name: instance_type.map(|t| t.as_str().to_string()),
+            qty,
+            // Regional reservations apply in any availability zone.
+            az: String::from(res.availability_zone().unwrap_or("Regional")),
+            expiry,
+            days_remaining: expiry.map(days_remaining),
+            term_years,
+            res_model,
+            res_recurring,
+            res_fixed,
+            res_yearly,
+            odm_rate,
+            odm_yearly,
+            saving_yearly: odm_yearly.zip(res_yearly).map(|(odm, res)| odm - res),
         });
+    }
 
-        total_odm_yearly = total_odm_yearly + odm_yearly;
-        total_res_yearly = total_res_yearly + res_yearly;
+    if !notes.is_empty() {
+        notes.push("Totals include only the amounts which are known.".to_string());
     }
 
     CalculationModel {
         elements,
+        notes,
     }
 }
 
-fn sum_recurring_charges(charges: &[RecurringCharge]) -> f32 {
+/// Sum the hourly recurring charges of a reservation.  Fails for a charge frequency other than
+/// hourly (currently the only one AWS uses), since it can't be converted to an hourly rate.
+fn sum_recurring_charges(charges: &[RecurringCharge]) -> Result<f32, JawsError> {
     let mut sum: f32 = 0.0;
 
     for charge in charges {
-        // Currently there is only Hourly
-        sum = sum
-            + match charge.frequency().unwrap() {
-                RecurringChargeFrequency::Hourly => charge.amount().unwrap() as f32,
-                _ => panic!(
-                    "Recurring charge frequency {} unknown!",
-                    charge.frequency().unwrap().as_str()
-                ),
-            };
+        sum += match charge.frequency() {
+            Some(RecurringChargeFrequency::Hourly) => charge.amount().unwrap_or_default() as f32,
+            frequency => return Err(JawsError::new(format!(
+                "recurring charge frequency '{}' isn't supported",
+                frequency.map(|f| f.as_str()).unwrap_or("none")))),
+        };
     }
 
-    sum
+    Ok(sum)
+}
+
+fn usd_or_unknown(amount: Option<f32>) -> MatrixCell {
+    amount.map(MatrixCell::usd).unwrap_or(MatrixCell::Unknown)
+}
+
+fn or_unknown(value: Option<impl Into<MatrixCell>>) -> MatrixCell {
+    value.map(Into::into).unwrap_or(MatrixCell::Unknown)
 }
 
 fn days_remaining(dt: DateTime<Utc>) -> i64 {
@@ -362,7 +496,120 @@ fn days_remaining(dt: DateTime<Utc>) -> i64 {
     diff.num_days()
 }
 
-fn format_money(amount: f32) -> String {
-    let usd = Money::from_decimal(Decimal::try_from(amount).unwrap(), iso::USD);
-    usd.round(2, Round::HalfUp).to_string()
+#[cfg(test)]
+mod tests {
+    use rust_decimal::Decimal;
+
+    use super::*;
+    use crate::backends::t_fixture_builder::{instance, reservation, FixtureBuilder};
+
+    /// Two running m5.large instances for one m5.large reservation, and one running c5.xlarge
+    /// for a reservation of two.
+    fn fixtures() -> FixtureBuilder {
+        FixtureBuilder::new()
+            .instance(instance("i-01", "m5.large", "running"))
+            .instance(instance("i-02", "m5.large", "running"))
+            .instance(instance("i-03", "c5.xlarge", "running"))
+            .instance(instance("i-04", "c5.xlarge", "stopped"))
+            .reservation(reservation("ri-1", "m5.large", 1, 0.068))
+            .reservation(reservation("ri-2", "c5.xlarge", 2, 0.1))
+            .price("m5.large", 0.107)
+            .price("c5.xlarge", 0.17)
+    }
+
+    /// The first column of each row below the header.
+    fn ids(matrix: &Matrix) -> Vec<String> {
+        matrix.rows.iter().flatten().skip(1)
+            .map(|row| row[0].as_ref().unwrap().to_string())
+            .collect()
+    }
+
+    fn dollars(cell: &MatrixCell) -> Decimal {
+        match cell {
+            MatrixCell::Money(amount, _) => amount.round_dp(2),
+            cell => panic!("{:?} isn't money", cell),
+        }
+    }
+
+    #[test]
+    fn thin_reservations_splits_covered_and_uncovered() {
+        let fixtures = fixtures().build();
+        let instances: Vec<Instance> = fixtures.instances.iter()
+            .filter(|i| i.state == "running")
+            .map(|i| i.to_sdk())
+            .collect();
+        let mut reservations: Vec<ReservedInstances> = fixtures.reserved_instances.iter().map(|r| r.to_sdk()).collect();
+
+        let (covered, uncovered) = thin_reservations(&instances, &mut reservations);
+
+        assert_eq!(covered, ["i-01", "i-03"]);
+        assert_eq!(uncovered, ["i-02"]);
+        assert_eq!(reservations.len(), 1);
+        assert_eq!(reservations[0].reserved_instances_id(), Some("ri-2"));
+        assert_eq!(reservations[0].instance_count(), Some(1));
+    }
+
+    #[tokio::test]
+    async fn show_unused_reports_coverage_of_running_instances() {
+        let (options, handler) = fixtures().handler(&["jaws", "res", "--show-unused"]);
+
+        let mut results = run_pooled(&options, vec![(options.clone(), handler)]).await;
+        let output = results.remove(0).unwrap().unwrap();
+
+        let headers: Vec<String> = output.matrices.iter().map(|m| m.header.as_ref().unwrap()[0].clone()).collect();
+        assert_eq!(headers, ["Active Reservations", "Covered Instances", "Uncovered Instances"]);
+        assert_eq!(ids(&output.matrices[0]), ["m5.large", "c5.xlarge"]);
+        assert_eq!(ids(&output.matrices[1]), ["i-01", "i-03"]);
+        assert_eq!(ids(&output.matrices[2]), ["i-02"]);
+    }
+
+    #[tokio::test]
+    async fn yearly_totals_sum_known_amounts() {
+        // No on-demand price is known for t3.micro, so its saving is unknown.
+        let (_, mut handler) = fixtures()
+            .reservation(reservation("ri-3", "t3.micro", 1, 0.005))
+            .handler(&["jaws", "res"]);
+        let reservations = handler.reservations_get_live().await.unwrap();
+
+        let model = calculate_model(&reservations, &mut handler).await;
+
+        let element = &model.elements[1];
+        assert_eq!((element.name.as_deref(), element.qty, element.az.as_str()), (Some("c5.xlarge"), Some(2), "Regional"));
+        assert_eq!(dollars(&MatrixCell::usd(element.res_yearly.unwrap())), Decimal::new(175200, 2));
+        assert_eq!(dollars(&MatrixCell::usd(element.odm_yearly.unwrap())), Decimal::new(297840, 2));
+        assert_eq!(model.elements[2].saving_yearly, None);
+        assert!(model.notes[0].starts_with("On-demand costs unknown"));
+
+        let matrix = ResCommand::new().get_reservations_matrix(&model);
+        let totals: Vec<(String, Decimal)> = matrix.aggregate_rows.unwrap().iter().skip(1)
+            .map(|total| (total.name.clone(), dollars(&total.value)))
+            .collect();
+        // 0.068 * 8760 + 0.1 * 8760 * 2 + 0.005 * 8760, and (0.107 - 0.068) * 8760 + (0.17 - 0.1) * 8760 * 2.
+        assert_eq!(totals, [
+            ("Total Yearly Spend".to_string(), Decimal::new(239148, 2)),
+            ("Total Yearly Saving".to_string(), Decimal::new(156804, 2)),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn missing_reservation_fields_are_unknown() {
+        let (_, mut handler) = fixtures().handler(&["jaws", "res"]);
+        let odd = ReservedInstances::builder().reserved_instances_id("ri-odd").build();
+
+        let model = calculate_model(std::slice::from_ref(&odd), &mut handler).await;
+
+        assert_eq!(model.notes[0], "Reservation ri-odd has no instance type, instance count, end date, duration, \
+                                    offering type, fixed price; shown as unknown.");
+        let matrix = ResCommand::new().get_reservations_matrix(&model);
+        let row = &matrix.rows.unwrap()[1];
+        assert_eq!(row[0], Some(MatrixCell::Unknown));
+        assert_eq!(row[2], Some("Regional".into()));
+        assert_eq!(row[3], Some(MatrixCell::Unknown));
+
+        // Nor can it cover an instance.
+        let instances = [instance("i-01", "m5.large", "running").to_sdk(), Instance::builder().build()];
+        let mut reservations = vec![odd];
+        assert_eq!(thin_reservations(&instances, &mut reservations), (vec![], vec!["i-01".to_string()]));
+        assert!(reservations.is_empty());
+    }
 }
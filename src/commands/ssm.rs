@@ -6,7 +6,7 @@ use signal_hook::{consts::SIGINT, consts::SIGTSTP, iterator::Signals};
 use subprocess::PopenConfig;
 
 use crate::{Options, SubCommands};
-use crate::errors::jaws_error::JawsError;
+use crate::errors::jaws_error::{JawsError, JawsErrorKind};
 use crate::t_command::Command;
 use crate::matrix_handlers::t_matrix_output::MatrixOutput;
 use crate::textutils::Textutil;
@@ -20,7 +20,7 @@ impl SSMCommand {
 
     /// Set up ctrl-C and ctrl-Z signal handlers so they are passed to the subprocess
     fn set_signal_handlers() {
-        let mut signals = Signals::new(&[SIGTSTP, SIGINT]).expect("Couldn't create Signals instance.");
+        let mut signals = Signals::new([SIGTSTP, SIGINT]).expect("Couldn't create Signals instance.");
 
         thread::spawn(move || {
             for _sig in signals.forever() {
@@ -55,10 +55,23 @@ impl Command for SSMCommand {
         // To get something running, we use the old Jaws 2 way of spawning SSM - spawn the AWS
         // SSM module.
 
-        let cmd_string = &["aws", "ssm", "start-session", "--target", instance];
+        let mut cmd_string = vec!["aws", "ssm", "start-session", "--target", instance];
+        if let Some(profile) = &options.profile {
+            cmd_string.extend(["--profile", profile.as_str()]);
+        }
+        if let Some(region) = &options.region {
+            cmd_string.extend(["--region", region.as_str()]);
+        }
+        if let Some(url) = options.endpoint_url("ssm") {
+            cmd_string.extend(["--endpoint-url", url]);
+        }
         Self::set_signal_handlers();
-        let popen_res = subprocess::Popen::create(cmd_string, PopenConfig::default());
-        popen_res.expect("Couldn't open the AWS SSM module, ensure it is installed.");
+        let popen_res = subprocess::Popen::create(&cmd_string, PopenConfig::default());
+        if let Err(e) = popen_res {
+            return Err(JawsError::with_kind(JawsErrorKind::ExternalTool,
+                "Couldn't open the AWS SSM module, ensure the AWS CLI and its Session Manager plugin are installed.".to_string())
+                .with_source(e));
+        }
 
         // Session is complete here
         let session_length = start_time.elapsed().as_secs();
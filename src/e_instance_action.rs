@@ -0,0 +1,49 @@
+use std::fmt::{Display, Formatter};
+
+/// Something which can be done to an EC2 instance's lifecycle, with `jaws ec2 <action>`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum InstanceAction {
+    Start,
+    Stop,
+    Reboot,
+    Terminate,
+    /// Stop, saving the contents of memory to disk.
+    Hibernate,
+}
+
+impl InstanceAction {
+    /// The state instances end up in, or None if the action doesn't change it (a reboot).
+    pub fn target_state(&self) -> Option<&'static str> {
+        match self {
+            InstanceAction::Start => Some("running"),
+            InstanceAction::Stop | InstanceAction::Hibernate => Some("stopped"),
+            InstanceAction::Reboot => None,
+            InstanceAction::Terminate => Some("terminated"),
+        }
+    }
+
+    /// The past tense, e.g. "Stopped".
+    pub fn done(&self) -> &'static str {
+        match self {
+            InstanceAction::Start => "Started",
+            InstanceAction::Stop => "Stopped",
+            InstanceAction::Reboot => "Rebooted",
+            InstanceAction::Terminate => "Terminated",
+            InstanceAction::Hibernate => "Hibernated",
+        }
+    }
+}
+
+/// The verb, in lower case, e.g. "stop".
+impl Display for InstanceAction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let verb = match self {
+            InstanceAction::Start => "start",
+            InstanceAction::Stop => "stop",
+            InstanceAction::Reboot => "reboot",
+            InstanceAction::Terminate => "terminate",
+            InstanceAction::Hibernate => "hibernate",
+        };
+        write!(f, "{}", verb)
+    }
+}
@@ -11,15 +11,33 @@ pub enum OutputFormat {
 
     /// Output in Json format
     Json,
+
+    /// Output as comma-separated values
+    Csv,
+
+    /// Output as tab-separated values
+    Tsv,
+
+    /// Output as GitHub-flavoured Markdown
+    Markdown,
+
+    /// Output as a self-contained HTML page
+    Html,
 }
 
 impl OutputFormat {
     pub fn supports_free_text_output(&self) -> bool {
         match *self {
             OutputFormat::Tabular => true,
-            OutputFormat::Json => false
+            OutputFormat::Json | OutputFormat::Csv | OutputFormat::Tsv |
+            OutputFormat::Markdown | OutputFormat::Html => false
         }
     }
+
+    /// Whether the format is a delimited one, which can be split into a file per table.
+    pub fn is_delimited(&self) -> bool {
+        matches!(self, OutputFormat::Csv | OutputFormat::Tsv)
+    }
 }
 
 // A block here from the example which set up the ValueEnum in code has been reduced (as
@@ -0,0 +1,40 @@
+use clap::ValueEnum;
+
+/// The border style of tables in tabular output.  Every style has a top border, which is
+/// where the column names go.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[derive(clap::ValueEnum)]
+pub enum TableStyle {
+    /// Box-drawing lines with rounded corners (default)
+    #[default]
+    Rounded,
+
+    /// Box-drawing lines with square corners
+    Sharp,
+
+    /// Box-drawing lines, with a line between every row
+    Modern,
+
+    /// Plain ASCII, for terminals and files which can't show box-drawing characters
+    Ascii,
+
+    /// Dotted lines
+    Dots,
+}
+
+impl std::fmt::Display for TableStyle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.to_possible_value()
+            .expect("no values are skipped")
+            .get_name()
+            .fmt(f)
+    }
+}
+
+impl std::str::FromStr for TableStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        <Self as ValueEnum>::from_str(s, true).map_err(|_| format!("invalid table style: {s}"))
+    }
+}
@@ -1,17 +1,191 @@
-use std::fmt::{Display, Formatter};
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
 
-/// Wraps any error that can be thrown during command execution.
+use aws_sdk_ec2::error::{ProvideErrorMetadata, SdkError};
+use serde::{Deserialize, Serialize};
+
+/// The broad category of a `JawsError`.  Each kind maps to a distinct process exit code, so
+/// that scripts can tell, for example, missing credentials from throttling.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JawsErrorKind {
+    /// A bug, or a failure which doesn't fit any other kind.
+    Internal,
+    /// The command line asked for something which can't be done (e.g. an unknown column).
+    Usage,
+    /// No credentials were found, or they were rejected or have expired.
+    Credentials,
+    /// The region or other configuration is missing or wrong, or AWS couldn't be reached.
+    Config,
+    /// The credentials are valid but don't allow the operation.
+    PermissionDenied,
+    /// AWS refused the request because of rate limiting.
+    Throttled,
+    /// A requested resource doesn't exist.
+    NotFound,
+    /// A program JAWS runs (e.g. the AWS CLI) couldn't be found or started.
+    ExternalTool,
+    /// Output couldn't be written.
+    Output,
+}
+
+impl JawsErrorKind {
+    /// The process exit code for this kind of error.  These are documented in the README and
+    /// must not change.  2 is also what the argument parser uses for usage errors.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            JawsErrorKind::Internal => 1,
+            JawsErrorKind::Usage => 2,
+            JawsErrorKind::Credentials => 3,
+            JawsErrorKind::Config => 4,
+            JawsErrorKind::PermissionDenied => 5,
+            JawsErrorKind::Throttled => 6,
+            JawsErrorKind::NotFound => 7,
+            JawsErrorKind::ExternalTool => 8,
+            JawsErrorKind::Output => 9,
+        }
+    }
+}
+
+impl Display for JawsErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            JawsErrorKind::Internal => "internal error",
+            JawsErrorKind::Usage => "usage error",
+            JawsErrorKind::Credentials => "credentials error",
+            JawsErrorKind::Config => "configuration error",
+            JawsErrorKind::PermissionDenied => "permission denied",
+            JawsErrorKind::Throttled => "throttled by AWS",
+            JawsErrorKind::NotFound => "not found",
+            JawsErrorKind::ExternalTool => "external tool error",
+            JawsErrorKind::Output => "output error",
+        };
+        write!(f, "{}", description)
+    }
+}
+
+/// Wraps any error that can be thrown during command execution.  The message is meant for the
+/// user; the underlying error, if there is one, is kept as the `source`.
 #[derive(Debug)]
 pub struct JawsError {
+    kind: JawsErrorKind,
     message: String,
+    source: Option<Box<dyn Error + Send + Sync>>,
 }
 
 impl JawsError {
+    /// An internal error with the given message.
     pub fn new(message: String) -> Self {
+        Self::with_kind(JawsErrorKind::Internal, message)
+    }
+
+    pub fn with_kind(kind: JawsErrorKind, message: String) -> Self {
         Self {
+            kind,
             message,
+            source: None,
+        }
+    }
+
+    /// Attach the underlying error.
+    pub fn with_source(mut self, source: impl Error + Send + Sync + 'static) -> Self {
+        self.source = Some(Box::new(source));
+        self
+    }
+
+    /// Wrap an error returned by an AWS SDK call, classifying it by its error code.  `context`
+    /// says what JAWS was doing, e.g. "Couldn't list EC2 instances".
+    pub fn from_sdk<E, R>(context: &str, error: SdkError<E, R>) -> Self
+    where
+        E: ProvideErrorMetadata + Error + Send + Sync + 'static,
+        R: Debug + Send + Sync + 'static,
+    {
+        let kind = classify_sdk_error(&error);
+        let detail = match (error.code(), error.message()) {
+            (Some(code), Some(message)) => format!("{}: {}", code, message),
+            (Some(code), None) => code.to_string(),
+            (None, Some(message)) => message.to_string(),
+            // Without a service response, the most useful message is usually the innermost one.
+            (None, None) => root_cause(&error),
+        };
+
+        let hint = match kind {
+            JawsErrorKind::Credentials =>
+                "\n\nEnsure your AWS credentials are set correctly in the environment, and that your session hasn't expired.",
+            JawsErrorKind::Throttled => "\n\nAWS is rate limiting requests; try again later.",
+            _ => "",
+        };
+
+        Self::with_kind(kind, format!("{}: {}{}", context, detail, hint)).with_source(error)
+    }
+
+    pub fn kind(&self) -> JawsErrorKind {
+        self.kind
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        self.kind.exit_code()
+    }
+}
+
+/// Work out what kind of failure an SDK error represents.  Service errors carry an error code;
+/// failures before a response arrives (no credentials, no network, bad endpoint) don't, so
+/// those are classified from the error chain.
+fn classify_sdk_error<E, R>(error: &SdkError<E, R>) -> JawsErrorKind
+where
+    E: ProvideErrorMetadata + Error + 'static,
+    R: Debug + 'static,
+{
+    if let Some(code) = error.code() {
+        return match code {
+            "ExpiredToken" | "ExpiredTokenException" | "InvalidClientTokenId" | "UnrecognizedClientException"
+            | "AuthFailure" | "SignatureDoesNotMatch" | "InvalidSignatureException" | "RequestExpired"
+            | "MissingAuthenticationToken" | "IncompleteSignature" => JawsErrorKind::Credentials,
+            "AccessDenied" | "AccessDeniedException" | "UnauthorizedOperation" | "UnauthorizedAccess"
+            | "OptInRequired" => JawsErrorKind::PermissionDenied,
+            "Throttling" | "ThrottlingException" | "RequestLimitExceeded" | "TooManyRequestsException"
+            | "RequestThrottled" | "RequestThrottledException" | "SlowDown" => JawsErrorKind::Throttled,
+            "InvalidRegion" | "InvalidEndpoint" | "UnsupportedOperation" => JawsErrorKind::Config,
+            code if code.contains("NotFound") || code.starts_with("NoSuch") => JawsErrorKind::NotFound,
+            _ => JawsErrorKind::Internal,
+        };
+    }
+
+    match error {
+        SdkError::ConstructionFailure(_) | SdkError::DispatchFailure(_) | SdkError::TimeoutError(_) => {
+            let chain = error_chain(error).to_lowercase();
+            if chain.contains("credential") || chain.contains("auth scheme") {
+                JawsErrorKind::Credentials
+            } else if chain.contains("region") {
+                JawsErrorKind::Config
+            } else if matches!(error, SdkError::ConstructionFailure(_)) {
+                JawsErrorKind::Internal
+            } else {
+                // The service couldn't be reached: usually a wrong region or endpoint, or no network.
+                JawsErrorKind::Config
+            }
         }
+        _ => JawsErrorKind::Internal,
+    }
+}
+
+/// The messages of an error and all its sources, joined.
+fn error_chain(error: &dyn Error) -> String {
+    let mut messages = vec![error.to_string()];
+    let mut source = error.source();
+    while let Some(e) = source {
+        messages.push(e.to_string());
+        source = e.source();
+    }
+    messages.join(": ")
+}
+
+/// The message of the innermost source of an error.
+fn root_cause(error: &dyn Error) -> String {
+    let mut cause = error;
+    while let Some(source) = cause.source() {
+        cause = source;
     }
+    cause.to_string()
 }
 
 impl Display for JawsError {
@@ -20,5 +194,15 @@ impl Display for JawsError {
     }
 }
 
-impl std::error::Error for JawsError {
-}
\ No newline at end of file
+impl Error for JawsError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.source.as_ref().map(|s| s.as_ref() as &(dyn Error + 'static))
+    }
+}
+
+impl From<std::io::Error> for JawsError {
+    fn from(error: std::io::Error) -> Self {
+        JawsError::with_kind(JawsErrorKind::Output, format!("Couldn't write output: {}", error))
+            .with_source(error)
+    }
+}
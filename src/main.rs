@@ -1,26 +1,45 @@
-use std::io::{stdout, Write};
+use std::io::{stderr, IsTerminal};
+use std::path::PathBuf;
 use std::string::ToString;
 
 use clap::Parser;
 use colored::*;
 use termion::clear::CurrentLine;
 
+use e_instance_action::InstanceAction;
 use e_output_format::OutputFormat;
+use e_table_style::TableStyle;
+use t_instance_filters::InstanceFilters;
+use t_output_target::OutputTarget;
 
-use crate::errors::jaws_error::JawsError;
+use crate::backends::e_backend::Backend;
+use crate::backends::t_recording_backend::save_recordings;
+use crate::backends::t_service_endpoint::ServiceEndpoint;
+use crate::errors::jaws_error::{JawsError, JawsErrorKind};
+use matrix_handlers::t_matrix_filter::MatrixFilter;
 use matrix_handlers::t_matrix_output::MatrixOutput;
 use matrix_handlers::t_matrix_output_driver::MatrixOutputDriver;
+use matrix_handlers::t_matrix_view::{MatrixView, SortKey};
 use t_command::Command;
 
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 mod t_aws_handler;
+mod backends;
 mod commands;
 mod errors;
 mod textutils;
 mod e_output_format;
+mod e_table_style;
+mod e_instance_action;
+mod t_output_target;
 mod t_command;
 mod t_ec2_instance;
+mod t_disk_cache;
+mod t_fanout;
+mod t_accounts;
+mod t_instance_filters;
+mod t_config;
 mod matrix_handlers;
 mod tui;
 
@@ -35,32 +54,220 @@ const LONG_ABOUT_TPL: &str = "JAWS - Nicer(ish) ways of interacting with AWS.\n\
                               \n\
                               Project: https://github.com/jhawksley/rjaws";
 
-#[derive(Parser, Debug)]
-#[clap(author, version, about, long_about = LONG_ABOUT_TPL)]
+#[derive(Parser, Debug, Clone)]
+#[clap(author, version, about, long_about = LONG_ABOUT_TPL, args_override_self = true)]
 pub struct Options {
     /// Output wider, more detailed data.  May cause slower execution in some cases.
     /// Not all commands support this.
-    #[clap(short, long, default_value_t = false, global = true)]
+    #[clap(short, long, default_value_t = false, global = true, overrides_with = "no_wide")]
     wide: bool,
 
+    /// Don't output wide data, even if the configuration file asks for it.
+    #[clap(long, global = true, overrides_with = "wide")]
+    no_wide: bool,
+
     /// A region to select (otherwise the default region is used)
     #[clap(short, long, global = true)]
     region: Option<String>,
 
+    /// Run the command in each of these regions at once, e.g. "eu-west-1,us-east-1", and merge
+    /// the results into one report with a Region column.  Totals are given for each region as
+    /// well as overall.
+    #[clap(long, global = true, value_delimiter = ',', conflicts_with_all = ["region", "all_regions"])]
+    regions: Vec<String>,
+
+    /// Like --regions, for every region enabled for the account.
+    #[clap(long, global = true, conflicts_with = "region")]
+    all_regions: bool,
+
+    /// Run the command in the account of this role, by assuming it, and merge the results into
+    /// one report with Account ID and Account columns.  May be repeated.
+    #[clap(long = "role-arn", global = true, value_name = "ARN")]
+    role_arns: Vec<String>,
+
+    /// A TOML file listing accounts to run the command in, like --role-arn, as
+    /// [[accounts]] tables with a role_arn and an optional alias.
+    #[clap(long, global = true, value_name = "FILE")]
+    accounts_file: Option<PathBuf>,
+
+    /// The role to assume for this run; set for each account when running in several.
+    #[clap(skip)]
+    assume_role: Option<String>,
+
+    /// The named AWS profile to use (otherwise AWS_PROFILE, or the default profile)
+    #[clap(short, long, global = true)]
+    profile: Option<String>,
+
+    /// Send every AWS request to this URL rather than to AWS, e.g. "http://localhost:4566"
+    /// for LocalStack.
+    #[clap(long, global = true, value_name = "URL")]
+    endpoint_url: Option<String>,
+
+    /// Send requests for one service to this URL, overriding --endpoint-url, as SERVICE=URL
+    /// (e.g. pricing=http://localhost:5000).  Services are sts, ec2, iam, pricing and ssm.
+    /// May be repeated.
+    #[clap(long, global = true, value_name = "SERVICE=URL")]
+    service_endpoint: Vec<ServiceEndpoint>,
+
     /// Output format for commands which support it
     #[clap(short, long, global = true, default_value = "tabular")]
     pub output_format: Option<OutputFormat>,
 
-    /// A subcommand to run
+    /// The border style of tables in tabular output.
+    #[clap(long, global = true)]
+    table_style: Option<TableStyle>,
+
+    /// Write the output to this file instead of the terminal.
+    #[clap(long, global = true)]
+    output_file: Option<PathBuf>,
+
+    /// Additionally write the output in another format, as FORMAT or FORMAT:FILE (e.g.
+    /// json:report.json).  Without a file, the output goes to the terminal.  May be repeated.
+    #[clap(long, global = true, value_name = "FORMAT[:FILE]")]
+    emit: Vec<OutputTarget>,
+
+    /// Show only these columns, in this order, e.g. "Name,Private IP,Type".  Columns are
+    /// named as in the table header (case-insensitive).
+    #[clap(long, global = true, value_delimiter = ',')]
+    columns: Vec<String>,
+
+    /// Sort rows by a column, as COLUMN, COLUMN:asc or COLUMN:desc.  Several keys may be
+    /// given, separated by commas, e.g. "Type,Days:desc".  Numbers, money and dates are
+    /// sorted by value.
+    #[clap(long, global = true, value_delimiter = ',', value_name = "COLUMN[:asc|desc]")]
+    sort_by: Vec<SortKey>,
+
+    /// Show only rows matching an expression such as "Status == running", "Days < 60" or
+    /// "Name ~ 'web-*'".  Operators are ==, !=, <, <=, >, >=, ~ (glob) and !~.  May be
+    /// repeated; rows must match every expression.  Totals are recomputed over the rows shown.
+    #[clap(long = "where", global = true, value_name = "EXPRESSION")]
+    where_: Vec<MatrixFilter>,
+
+    /// For commands which output several tables, output only this one.  Tables are selected
+    /// by 1-based position or by title, e.g. "Uncovered Instances".
+    #[clap(short, long, global = true)]
+    matrix: Option<String>,
+
+    /// For csv and tsv output, write each table to its own file in this directory rather
+    /// than to the terminal.
+    #[clap(long, global = true)]
+    split_dir: Option<PathBuf>,
+
+    /// The maximum number of AWS lookups to make at once, e.g. when gathering the details
+    /// for --wide output.  Lower this if AWS throttles requests.
+    #[clap(long, global = true, default_value_t = 8, value_parser = clap::value_parser!(u16).range(1..))]
+    concurrency: u16,
+
+    /// Don't read or write the cache of instance type specs and prices; fetch everything from
+    /// AWS.
+    #[clap(long, global = true, conflicts_with = "refresh_cache")]
+    no_cache: bool,
+
+    /// Ignore cached instance type specs and prices, fetch them again, and update the cache.
+    #[clap(long, global = true)]
+    refresh_cache: bool,
+
+    /// Where AWS data comes from: "sdk" (the default) calls AWS, "fixtures:DIR" reads canned
+    /// data from JSON files in DIR and works offline.
+    #[clap(long, global = true, default_value = "sdk", value_name = "sdk|fixtures:DIR")]
+    backend: Backend,
+
+    /// Record every AWS response received during this run in FILE, so that the run can be
+    /// reproduced offline with --replay.  Cached data isn't used while recording.
+    #[clap(long, global = true, value_name = "FILE", conflicts_with = "replay")]
+    record: Option<PathBuf>,
+
+    /// With --record, replace AWS account IDs in the recording with made-up ones.
+    #[clap(long, global = true, requires = "record")]
+    scrub_account_ids: bool,
+
+    /// Serve AWS responses from a file made with --record, without network access.
+    #[clap(long, global = true, value_name = "FILE", conflicts_with = "backend")]
+    replay: Option<PathBuf>,
+
+    /// Read defaults and presets from this file rather than ~/.config/jaws/config.toml.
+    #[clap(long, global = true, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// A subcommand to run, or @NAME to run a preset from the configuration file
     #[clap(subcommand)]
     subcommand: SubCommands,
 }
 
-// Subcommands and their options
-#[derive(clap::Subcommand, Debug)]
+impl Options {
+    /// All the places matrix output should be written: the primary `--output-format` (to
+    /// `--output-file` or the terminal), followed by any `--emit` targets.
+    pub fn output_targets(&self) -> Vec<OutputTarget> {
+        let mut targets = vec![OutputTarget {
+            format: self.output_format.unwrap(),
+            file: self.output_file.clone(),
+        }];
+        targets.extend(self.emit.iter().cloned());
+        targets
+    }
+
+    /// Check the combinations of options which can't be checked as they're parsed, since
+    /// the configuration file can supply some of them.
+    pub fn check(&self) -> Result<(), JawsError> {
+        if self.split_dir.is_some() && !self.output_targets().iter().any(|t| t.format.is_delimited()) {
+            return Err(JawsError::with_kind(JawsErrorKind::Usage,
+                "--split-dir only applies to csv and tsv output.\n\nUse it with -o csv, -o tsv or --emit csv.".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Whether the command is to be run in several regions or accounts.
+    pub fn is_fanned_out(&self) -> bool {
+        !self.regions.is_empty() || self.all_regions || !self.role_arns.is_empty() || self.accounts_file.is_some()
+    }
+
+    /// The endpoint URL to use for `service` ("ec2", "pricing" etc.), if it isn't the real one.
+    pub fn endpoint_url(&self, service: &str) -> Option<&str> {
+        self.service_endpoint.iter()
+            .rfind(|e| e.service == service)
+            .map(|e| e.url.as_str())
+            .or(self.endpoint_url.as_deref())
+    }
+
+    /// Whether any AWS requests go somewhere other than AWS.
+    pub fn has_custom_endpoints(&self) -> bool {
+        self.endpoint_url.is_some() || !self.service_endpoint.is_empty()
+    }
+
+    /// The AWS profile in use, if one was named with `--profile` or `AWS_PROFILE`.
+    pub fn active_profile(&self) -> Option<String> {
+        self.profile.clone()
+            .or_else(|| std::env::var("AWS_PROFILE").ok())
+            .filter(|profile| !profile.is_empty())
+    }
+}
+
+// Subcommands and their options.  The variant names double as the command names, so they
+// keep their acronym casing.
+#[derive(clap::Subcommand, Debug, Clone)]
+#[allow(clippy::upper_case_acronyms)]
 enum SubCommands {
-    /// List inventory of EC2 instances
-    EC2,
+    /// List inventory of EC2 instances, or start, stop, reboot, terminate or hibernate them
+    #[command(args_conflicts_with_subcommands = true)]
+    EC2 {
+        #[clap(subcommand)]
+        action: Option<Box<Ec2Action>>,
+
+        #[clap(flatten)]
+        filters: InstanceFilters,
+
+        /// Leave out terminated instances.
+        #[clap(long, conflicts_with = "states")]
+        hide_terminated: bool,
+
+        /// Show each of these tags as a column, e.g. "Owner,Team,CostCenter".
+        #[clap(long, value_delimiter = ',', value_name = "TAGS")]
+        tag_columns: Vec<String>,
+
+        /// Show all of each instance's tags in a single Tags column.
+        #[clap(long)]
+        all_tags: bool,
+    },
 
     /// Start an SSM (login) session with an instance.
     SSM {
@@ -75,71 +282,189 @@ enum SubCommands {
     RES {
         /// Output additional information about unused reservations
         #[clap(short, long, default_value_t = false)]
-        show_unused: bool
+        show_unused: bool,
+
+        /// When run in several accounts, work out each account's coverage from its own
+        /// reservations only, for accounts which don't share them.  Otherwise reservations are
+        /// shared between the accounts, as under consolidated billing.
+        #[clap(long)]
+        separate_accounts: bool,
     },
 
     /// Generate some sample matrices for testing
     MTC,
+
+    /// Manage the on-disk cache of instance type specs and prices
+    CACHE {
+        #[clap(subcommand)]
+        action: CacheAction,
+    },
+}
+
+impl SubCommands {
+    /// Whether the command reports on a single region, and so can be run across several.
+    fn is_regional(&self) -> bool {
+        matches!(self, SubCommands::EC2 { action: None, .. } | SubCommands::RES { .. })
+    }
+}
+
+#[derive(clap::Subcommand, Debug, Clone)]
+enum Ec2Action {
+    /// Start stopped instances
+    Start(LifecycleArgs),
+
+    /// Stop running instances
+    Stop(LifecycleArgs),
+
+    /// Reboot running instances
+    Reboot(LifecycleArgs),
+
+    /// Terminate instances, for good
+    Terminate(LifecycleArgs),
+
+    /// Stop running instances, saving their memory to disk.  Hibernation must have been
+    /// enabled when the instances were launched.
+    Hibernate(LifecycleArgs),
+}
+
+impl Ec2Action {
+    fn action(&self) -> InstanceAction {
+        match self {
+            Ec2Action::Start(_) => InstanceAction::Start,
+            Ec2Action::Stop(_) => InstanceAction::Stop,
+            Ec2Action::Reboot(_) => InstanceAction::Reboot,
+            Ec2Action::Terminate(_) => InstanceAction::Terminate,
+            Ec2Action::Hibernate(_) => InstanceAction::Hibernate,
+        }
+    }
+
+    fn args(&self) -> &LifecycleArgs {
+        match self {
+            Ec2Action::Start(args) | Ec2Action::Stop(args) | Ec2Action::Reboot(args)
+            | Ec2Action::Terminate(args) | Ec2Action::Hibernate(args) => args,
+        }
+    }
+}
+
+/// The instances to act on, and how.
+#[derive(clap::Args, Debug, Clone)]
+struct LifecycleArgs {
+    /// Instance IDs (i-...) or Name tag values, which may contain the wildcards * and ?
+    targets: Vec<String>,
+
+    /// Act on the instances matching these filters (or, with targets, only those of the
+    /// targets which match them).
+    #[clap(flatten)]
+    filters: InstanceFilters,
+
+    /// Don't ask for confirmation.  Needed when input isn't a terminal.
+    #[clap(short, long)]
+    yes: bool,
+
+    /// Only ask AWS whether the request would succeed; change nothing.
+    #[clap(long)]
+    dry_run: bool,
+
+    /// Don't wait for the instances to reach their new state.
+    #[clap(long)]
+    no_wait: bool,
+
+    /// How long to wait for the instances to reach their new state, in seconds.
+    #[clap(long, default_value_t = 600, value_name = "SECONDS")]
+    timeout: u64,
+}
+
+#[derive(clap::Subcommand, Debug, Clone)]
+enum CacheAction {
+    /// Show what's cached, for which regions, and how old it is
+    Status,
+
+    /// Remove all cached data
+    Clear,
 }
 
 // Main: starts here. We need tokio because the AWS libraries need it.
 #[tokio::main]
 async fn main() {
 
-    // Parse options
-    let mut options = Options::parse();
+    // Parse options, with defaults and presets from the configuration file
+    let mut options = t_config::parse_options().unwrap_or_else(|e| handle_and_exit(e));
+    if let Err(e) = options.check() {
+        handle_and_exit(e)
+    }
 
-    // Switch based on the selected subcommand
-    let command: Option<Box<dyn Command>> = match &options.subcommand {
-        SubCommands::EC2 => Some(Box::new(commands::ec2::EC2Command::new(&options).await)),
-        SubCommands::GCI => Some(Box::new(commands::gci::GCICommand)),
-        SubCommands::SSM { instance_id: _ } => {
-            Some(Box::new(commands::ssm::SSMCommand::new()))
-        }
-        SubCommands::RES { show_unused: _ } => Some(Box::new(commands::res::ResCommand::new())),
-        SubCommands::MTC => Some(Box::new(commands::matrix_test_command::MatrixTestCommand {}))
+    let result = if options.is_fanned_out() {
+        t_fanout::run_fanned_out(&options).await
+    } else {
+        run_command(&mut options).await
     };
 
-    match command {
-        Some(mut c) => {
-            match c.run(&mut options).await {
-                Ok(_) => {
-                    // Command ran to completion.  Check whether it requires Matrix Output
-                    // to be decoded and output.
-                    if let Some(matrix_output) = c.get_matrix_output() {
-                        handle_matrix_output(options.output_format.unwrap(), matrix_output);
-                    }
-                }
-                Err(e) => handle_and_panic(e),
+    // Save any recording, even of a failed run.  The command's own error takes precedence.
+    let saved = save_recordings(options.scrub_account_ids);
+    let result = result.and_then(|output| saved.map(|_| output));
+
+    match result {
+        // The command ran to completion.  If it produced matrix output, decode and output it.
+        Ok(Some(mut matrix_output)) => {
+            if let Err(e) = MatrixView::new(&options).apply(&mut matrix_output)
+                .and_then(|_| handle_matrix_output(&options, matrix_output)) {
+                handle_and_exit(e)
             }
         }
-        None => handle_and_panic(JawsError::new(format!(
-            "Command '{:?}' not found",
-            options.subcommand
-        ))),
+        Ok(None) => {}
+        Err(e) => handle_and_exit(e),
     }
 }
 
-pub fn handle_and_panic(error: JawsError) -> ! {
+/// Run the selected command, returning its matrix output, if it has any.
+pub async fn run_command(options: &mut Options) -> Result<Option<MatrixOutput>, JawsError> {
+    // Switch based on the selected subcommand
+    let mut command: Box<dyn Command> = match &options.subcommand {
+        SubCommands::EC2 { action: Some(_), .. } =>
+            Box::new(commands::ec2_lifecycle::Ec2LifecycleCommand::new(options).await?),
+        SubCommands::EC2 { .. } => Box::new(commands::ec2::EC2Command::new(options).await?),
+        SubCommands::GCI => Box::new(commands::gci::GCICommand),
+        SubCommands::SSM { instance_id: _ } => Box::new(commands::ssm::SSMCommand::new()),
+        SubCommands::RES { .. } => Box::new(commands::res::ResCommand::new()),
+        SubCommands::MTC => Box::new(commands::matrix_test_command::MatrixTestCommand {}),
+        SubCommands::CACHE { action: _ } => Box::new(commands::cache::CacheCommand::new()),
+    };
+
+    command.run(options).await?;
+    Ok(command.get_matrix_output())
+}
+
+/// Report an error and exit with the process exit code for its kind (see `JawsErrorKind`).
+pub fn handle_and_exit(error: JawsError) -> ! {
     // This was a call to txt_line_clear, but since the rejig of text output, and the fact that
-    // this function should probably have as few dependencies as possible, I've inlined it.
-    print!("\r{}", CurrentLine);
-    _ = stdout().flush();
-
-    // Output the abort
-    println!(
-        "{}\n\n{}\n",
-        "*** PANIC ***".red().bold().underline(),
-        "Software panicked with the following error:".red()
-    );
-    println!("{}\n", error.to_string().red());
-
-    // ... and halt with error.
-    panic!()
+    // this function should probably have as few dependencies as possible, I've inlined it.  The
+    // progress line is cleared through stderr, which shares the terminal with it.
+    if stderr().is_terminal() {
+        eprint!("\r{}", CurrentLine);
+    }
+
+    // Errors go to stderr, so that they never end up in redirected output.
+    eprintln!("{} {}\n", "Error:".red().bold(), format!("({})", error.kind()).red());
+    eprintln!("{}\n", error.to_string().red());
+
+    let mut source = std::error::Error::source(&error);
+    while let Some(cause) = source {
+        eprintln!("  {} {}", "caused by:".dimmed(), cause.to_string().dimmed());
+        source = cause.source();
+    }
+
+    std::process::exit(error.exit_code())
 }
 
-pub fn handle_matrix_output(output_format: OutputFormat,
-                            matrix_output: MatrixOutput) {
-    let mut handler = MatrixOutputDriver {output_format, matrix_output};
-    handler.output();
+pub fn handle_matrix_output(options: &Options,
+                            matrix_output: MatrixOutput) -> Result<(), JawsError> {
+    let mut handler = MatrixOutputDriver {
+        targets: options.output_targets(),
+        matrix_output,
+        matrix_selector: options.matrix.clone(),
+        split_dir: options.split_dir.clone(),
+        profile: options.active_profile(),
+        table_style: options.table_style.unwrap_or_default(),
+    };
+    handler.output()
 }
@@ -0,0 +1,263 @@
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use rust_decimal::prelude::*;
+use rusty_money::iso::Currency;
+use rusty_money::{iso, Money};
+
+/// A single typed value in a `Matrix`.  Commands fill matrices with these rather than with
+/// preformatted strings, so that each output handler can decide how a value should be rendered:
+/// the tabular handler can right-align numbers, while JSON can emit them as real numbers.
+///
+/// The `Display` implementation gives the default, human-readable rendering, which handlers
+/// use for any type they have no better representation for.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MatrixCell {
+    String(String),
+    Integer(i64),
+    Decimal(f64),
+    /// An amount of money in a given currency.  Rendered to two decimal places.
+    Money(Decimal, &'static Currency),
+    /// Rendered as Yes/No by default.
+    Bool(bool),
+    DateTime(DateTime<Utc>),
+    Duration(TimeDelta),
+    /// A compound value (e.g. vCPU/GiB).  Rendered as slash-separated items by default.
+    List(Vec<MatrixCell>),
+    /// Named values (e.g. an instance's tags), in order.  Rendered as comma-separated
+    /// key=value pairs by default.
+    Map(Vec<(String, MatrixCell)>),
+    /// A value which should be present but couldn't be determined (e.g. because an AWS call
+    /// failed).  Commands explain why in the matrix notes.  Ignored by sums.
+    Unknown,
+}
+
+impl MatrixCell {
+    /// Convenience constructor for US dollar amounts, which is what the AWS APIs return.
+    /// Amounts which can't be represented as a decimal (NaN, infinities) become zero.
+    pub fn usd(amount: f32) -> Self {
+        MatrixCell::Money(Decimal::from_f32(amount).unwrap_or_default(), iso::USD)
+    }
+
+    /// True if the value is a quantity, which handlers may choose to align or format
+    /// differently from text.
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, MatrixCell::Integer(_) | MatrixCell::Decimal(_) |
+            MatrixCell::Money(_, _) | MatrixCell::Duration(_))
+    }
+}
+
+impl MatrixCell {
+    /// The zero value of this cell's numeric type, for starting a sum.  Non-numeric
+    /// cells have an integer zero.
+    pub fn zero(&self) -> MatrixCell {
+        match self {
+            MatrixCell::Decimal(_) => MatrixCell::Decimal(0.0),
+            MatrixCell::Money(_, currency) => MatrixCell::Money(Decimal::ZERO, currency),
+            MatrixCell::Duration(_) => MatrixCell::Duration(TimeDelta::zero()),
+            _ => MatrixCell::Integer(0),
+        }
+    }
+
+    /// Add a numeric cell to this one.  Integers are promoted to decimals, money or durations
+    /// (as seconds) as needed; anything which isn't a compatible quantity is ignored.  Money in
+    /// different currencies can't be added, so makes the total unknown.
+    pub fn add(self, other: &MatrixCell) -> MatrixCell {
+        match (self, other) {
+            (MatrixCell::Integer(a), MatrixCell::Integer(b)) => MatrixCell::Integer(a + b),
+            (MatrixCell::Integer(a), MatrixCell::Decimal(b)) => MatrixCell::Decimal(a as f64 + b),
+            (MatrixCell::Decimal(a), MatrixCell::Integer(b)) => MatrixCell::Decimal(a + *b as f64),
+            (MatrixCell::Decimal(a), MatrixCell::Decimal(b)) => MatrixCell::Decimal(a + b),
+            (MatrixCell::Integer(a), MatrixCell::Money(b, currency)) =>
+                MatrixCell::Money(Decimal::from(a) + b, currency),
+            (MatrixCell::Money(a, currency), MatrixCell::Integer(b)) =>
+                MatrixCell::Money(a + Decimal::from(*b), currency),
+            (MatrixCell::Money(a, currency), MatrixCell::Money(b, other_currency)) =>
+                if currency.iso_alpha_code == other_currency.iso_alpha_code {
+                    MatrixCell::Money(a + b, currency)
+                } else {
+                    MatrixCell::Unknown
+                },
+            (MatrixCell::Integer(a), MatrixCell::Duration(b)) =>
+                MatrixCell::Duration(TimeDelta::seconds(a) + *b),
+            (MatrixCell::Duration(a), MatrixCell::Integer(b)) =>
+                MatrixCell::Duration(a + TimeDelta::seconds(*b)),
+            (MatrixCell::Duration(a), MatrixCell::Duration(b)) => MatrixCell::Duration(a + *b),
+            (total, _) => total,
+        }
+    }
+
+    /// Order two cells by value rather than by their rendering: numbers numerically, dates
+    /// chronologically, text case-insensitively.  Cells of different kinds (which shouldn't
+    /// normally share a column) are ordered numbers first, then dates, flags, text, lists, maps
+    /// and unknown values.  Maps are ordered by their rendering.
+    pub fn sort_cmp(&self, other: &MatrixCell) -> Ordering {
+        match (self.sort_number(), other.sort_number()) {
+            (Some(a), Some(b)) => return a.total_cmp(&b),
+            (Some(_), None) => return Ordering::Less,
+            (None, Some(_)) => return Ordering::Greater,
+            (None, None) => {}
+        }
+
+        match (self, other) {
+            (MatrixCell::DateTime(a), MatrixCell::DateTime(b)) => a.cmp(b),
+            (MatrixCell::Bool(a), MatrixCell::Bool(b)) => a.cmp(b),
+            (MatrixCell::String(a), MatrixCell::String(b)) =>
+                a.to_lowercase().cmp(&b.to_lowercase()).then_with(|| a.cmp(b)),
+            (MatrixCell::List(a), MatrixCell::List(b)) => {
+                for (x, y) in a.iter().zip(b.iter()) {
+                    let ordering = x.sort_cmp(y);
+                    if ordering != Ordering::Equal {
+                        return ordering;
+                    }
+                }
+                a.len().cmp(&b.len())
+            }
+            (MatrixCell::Map(_), MatrixCell::Map(_)) =>
+                self.to_string().to_lowercase().cmp(&other.to_string().to_lowercase()),
+            _ => self.sort_rank().cmp(&other.sort_rank()),
+        }
+    }
+
+    /// The value of a numeric cell as a float, for ordering.
+    fn sort_number(&self) -> Option<f64> {
+        match self {
+            MatrixCell::Integer(i) => Some(*i as f64),
+            MatrixCell::Decimal(d) => Some(*d),
+            MatrixCell::Money(amount, _) => amount.to_f64(),
+            MatrixCell::Duration(d) => Some(d.num_milliseconds() as f64),
+            _ => None,
+        }
+    }
+
+    fn sort_rank(&self) -> u8 {
+        match self {
+            MatrixCell::Integer(_) | MatrixCell::Decimal(_) |
+            MatrixCell::Money(_, _) | MatrixCell::Duration(_) => 0,
+            MatrixCell::DateTime(_) => 1,
+            MatrixCell::Bool(_) => 2,
+            MatrixCell::String(_) => 3,
+            MatrixCell::List(_) => 4,
+            MatrixCell::Map(_) => 5,
+            MatrixCell::Unknown => 6,
+        }
+    }
+}
+
+impl Display for MatrixCell {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MatrixCell::String(s) => write!(f, "{}", s),
+            MatrixCell::Integer(i) => write!(f, "{}", i),
+            MatrixCell::Decimal(d) => write!(f, "{}", d),
+            MatrixCell::Money(amount, currency) => {
+                // Rescaling pads to two places, so that e.g. 1234.5 renders as $1,234.50.
+                let mut amount = amount.round_dp_with_strategy(2, RoundingStrategy::MidpointAwayFromZero);
+                amount.rescale(2);
+                write!(f, "{}", Money::from_decimal(amount, *currency))
+            }
+            MatrixCell::Bool(b) => write!(f, "{}", if *b { "Yes" } else { "No" }),
+            MatrixCell::DateTime(dt) => write!(f, "{}", dt),
+            MatrixCell::Duration(d) => {
+                let seconds = d.num_seconds();
+                write!(f, "{}d{}h{}m", seconds / 86400, (seconds / 3600) % 24, (seconds / 60) % 60)
+            }
+            MatrixCell::List(items) => {
+                let rendered: Vec<String> = items.iter().map(|i| i.to_string()).collect();
+                write!(f, "{}", rendered.join("/"))
+            }
+            MatrixCell::Map(entries) => {
+                let rendered: Vec<String> = entries.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+                write!(f, "{}", rendered.join(", "))
+            }
+            MatrixCell::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+impl From<String> for MatrixCell {
+    fn from(value: String) -> Self {
+        MatrixCell::String(value)
+    }
+}
+
+impl From<&str> for MatrixCell {
+    fn from(value: &str) -> Self {
+        MatrixCell::String(value.to_string())
+    }
+}
+
+impl From<i64> for MatrixCell {
+    fn from(value: i64) -> Self {
+        MatrixCell::Integer(value)
+    }
+}
+
+impl From<i32> for MatrixCell {
+    fn from(value: i32) -> Self {
+        MatrixCell::Integer(value as i64)
+    }
+}
+
+impl From<f64> for MatrixCell {
+    fn from(value: f64) -> Self {
+        MatrixCell::Decimal(value)
+    }
+}
+
+impl From<bool> for MatrixCell {
+    fn from(value: bool) -> Self {
+        MatrixCell::Bool(value)
+    }
+}
+
+impl From<DateTime<Utc>> for MatrixCell {
+    fn from(value: DateTime<Utc>) -> Self {
+        MatrixCell::DateTime(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn money(amount: i64, currency: &'static Currency) -> MatrixCell {
+        MatrixCell::Money(Decimal::from(amount), currency)
+    }
+
+    /// Adding the cells either way round gives `sum`.
+    fn assert_sum(a: MatrixCell, b: MatrixCell, sum: MatrixCell) {
+        assert_eq!(a.clone().add(&b), sum);
+        assert_eq!(b.add(&a), sum);
+    }
+
+    #[test]
+    fn adds_integers_to_decimals() {
+        assert_sum(MatrixCell::Integer(2), MatrixCell::Decimal(0.5), MatrixCell::Decimal(2.5));
+    }
+
+    #[test]
+    fn adds_integers_to_money() {
+        assert_sum(MatrixCell::Integer(2), money(3, iso::USD), money(5, iso::USD));
+    }
+
+    #[test]
+    fn adds_integers_to_durations_as_seconds() {
+        assert_sum(MatrixCell::Integer(30), MatrixCell::Duration(TimeDelta::minutes(1)),
+                   MatrixCell::Duration(TimeDelta::seconds(90)));
+    }
+
+    #[test]
+    fn money_in_different_currencies_is_unknown() {
+        assert_sum(money(1, iso::USD), money(2, iso::USD), money(3, iso::USD));
+        assert_sum(money(1, iso::USD), money(2, iso::EUR), MatrixCell::Unknown);
+        assert_eq!(money(1, iso::USD).add(&money(2, iso::EUR)).add(&money(3, iso::USD)), MatrixCell::Unknown);
+    }
+
+    #[test]
+    fn ignores_incompatible_cells() {
+        assert_eq!(MatrixCell::Integer(1).add(&MatrixCell::String("x".to_string())), MatrixCell::Integer(1));
+        assert_eq!(MatrixCell::Integer(1).add(&MatrixCell::Unknown), MatrixCell::Integer(1));
+    }
+}
@@ -1,6 +1,13 @@
 pub mod tr_matrix_output_handler;
 pub mod t_tabular_output;
 pub mod t_json_output;
+pub mod t_delimited_output;
+pub mod t_markdown_output;
+pub mod t_html_output;
 pub mod t_matrix_output_driver;
 pub mod t_matrix_output;
+pub mod e_matrix_cell;
+pub mod t_matrix_view;
+pub mod t_matrix_filter;
 
+pub mod t_matrix_merge;
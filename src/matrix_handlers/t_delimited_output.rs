@@ -0,0 +1,126 @@
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+
+use chrono::SecondsFormat;
+use rust_decimal::RoundingStrategy;
+
+use crate::errors::jaws_error::{JawsError, JawsErrorKind};
+use crate::matrix_handlers::e_matrix_cell::MatrixCell;
+use crate::matrix_handlers::t_matrix_output::{Matrix, MatrixOutput};
+use crate::matrix_handlers::tr_matrix_output_handler::TrMatrixOutputHandler;
+
+/// Renders matrices as delimiter-separated values (CSV or TSV), one line per row, for
+/// loading into spreadsheets.  Only the row data is written - report headers, footers,
+/// aggregates and notes have no place in a flat table and are omitted.
+///
+/// A delimited file can only hold one table.  If the output contains several matrices they
+/// are written one after the other, separated by a blank line, unless `split_dir` is set,
+/// in which case each matrix is written to its own file in that directory.
+pub struct DelimitedOutput {
+    pub delimiter: char,
+    pub extension: &'static str,
+    pub split_dir: Option<PathBuf>,
+}
+
+impl DelimitedOutput {
+    pub fn csv(split_dir: Option<PathBuf>) -> Self {
+        Self { delimiter: ',', extension: "csv", split_dir }
+    }
+
+    pub fn tsv(split_dir: Option<PathBuf>) -> Self {
+        Self { delimiter: '\t', extension: "tsv", split_dir }
+    }
+}
+
+impl TrMatrixOutputHandler for DelimitedOutput {
+    fn output(&mut self, matrix_output: &MatrixOutput, out: &mut dyn Write) -> Result<(), JawsError> {
+        match &self.split_dir {
+            None => {
+                let tables: Vec<String> = matrix_output.matrices.iter()
+                    .map(|m| self.render_matrix(m))
+                    .collect();
+                write!(out, "{}", tables.join("\n"))?;
+            }
+            Some(dir) => {
+                fs::create_dir_all(dir).map_err(|e| JawsError::with_kind(JawsErrorKind::Output,
+                    format!("Couldn't create output directory '{}': {}", dir.display(), e)).with_source(e))?;
+
+                for (i, matrix) in matrix_output.matrices.iter().enumerate() {
+                    let path = dir.join(format!("{}.{}", matrix_file_stem(i, matrix), self.extension));
+                    fs::write(&path, self.render_matrix(matrix)).map_err(|e| JawsError::with_kind(JawsErrorKind::Output,
+                        format!("Couldn't write '{}': {}", path.display(), e)).with_source(e))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl DelimitedOutput {
+    fn render_matrix(&self, matrix: &Matrix) -> String {
+        let mut out = String::new();
+
+        for row in matrix.rows.iter().flatten() {
+            let fields: Vec<String> = row.iter()
+                .map(|cell| match cell {
+                    Some(cell) => self.escape(&cell_to_field(cell)),
+                    None => String::new(),
+                })
+                .collect();
+            out.push_str(&fields.join(&self.delimiter.to_string()));
+            out.push('\n');
+        }
+
+        out
+    }
+
+    fn escape(&self, field: &str) -> String {
+        if self.delimiter == '\t' {
+            // TSV has no quoting mechanism, so characters which would break the
+            // row structure are replaced with spaces.
+            field.replace(['\t', '\n', '\r'], " ")
+        } else if field.contains([self.delimiter, '"', '\n', '\r']) {
+            // RFC 4180: quote the field and double any embedded quotes.
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            field.to_string()
+        }
+    }
+}
+
+/// Render a cell as a spreadsheet-friendly field.  Quantities are written as plain numbers
+/// (money without currency symbols or thousands separators) so that they import as numbers.
+fn cell_to_field(cell: &MatrixCell) -> String {
+    match cell {
+        MatrixCell::Money(amount, _) => {
+            let mut amount = amount.round_dp_with_strategy(2, RoundingStrategy::MidpointAwayFromZero);
+            amount.rescale(2);
+            amount.to_string()
+        }
+        MatrixCell::DateTime(dt) => dt.to_rfc3339_opts(SecondsFormat::Secs, true),
+        MatrixCell::Duration(d) => d.num_seconds().to_string(),
+        MatrixCell::List(items) => items.iter().map(cell_to_field).collect::<Vec<String>>().join("/"),
+        _ => cell.to_string(),
+    }
+}
+
+/// Produce a file name stem such as `1-active-reservations` for a matrix, from its position
+/// and the first line of its header.
+fn matrix_file_stem(index: usize, matrix: &Matrix) -> String {
+    let title = matrix.header.as_ref()
+        .and_then(|h| h.first())
+        .map(|h| h.to_lowercase()
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<&str>>()
+            .join("-"))
+        .unwrap_or_default();
+
+    if title.is_empty() {
+        format!("{}", index + 1)
+    } else {
+        format!("{}-{}", index + 1, title)
+    }
+}
@@ -0,0 +1,224 @@
+use std::io::Write;
+
+use chrono::Utc;
+use rust_decimal::prelude::ToPrimitive;
+use whoami::{fallible, username};
+
+use crate::errors::jaws_error::JawsError;
+use crate::matrix_handlers::e_matrix_cell::MatrixCell;
+use crate::matrix_handlers::t_matrix_output::{Matrix, MatrixFooter, MatrixHeader, MatrixOutput, MatrixRowT};
+use crate::matrix_handlers::tr_matrix_output_handler::TrMatrixOutputHandler;
+
+/// Styles for the report.  Everything is inlined so that the page is a single file which can
+/// be attached to an email and opened anywhere.
+const STYLE: &str = r#"
+body { font-family: -apple-system, "Segoe UI", Helvetica, Arial, sans-serif; margin: 2em; color: #222; }
+header, footer { display: flex; justify-content: space-between; color: #666; font-size: 0.85em; }
+header { border-bottom: 1px solid #ccc; padding-bottom: 0.5em; }
+footer { border-top: 1px solid #ccc; padding-top: 0.5em; margin-top: 2em; }
+h1 { text-align: center; font-weight: 600; }
+h2 { font-weight: 600; margin-top: 2em; }
+table { border-collapse: collapse; margin: 1em 0; font-size: 0.9em; }
+th, td { border: 1px solid #ddd; padding: 0.3em 0.7em; text-align: left; white-space: nowrap; }
+th { background: #f3f3f3; }
+table.data th { cursor: pointer; user-select: none; }
+table.data th[data-dir="asc"]::after { content: " \25B2"; }
+table.data th[data-dir="desc"]::after { content: " \25BC"; }
+table.data tbody tr:nth-child(even) { background: #fafafa; }
+td.num { text-align: right; font-variant-numeric: tabular-nums; }
+table.aggregates th { text-align: right; background: none; }
+p.footer-text { text-align: center; }
+"#;
+
+/// Click-to-sort for data tables.  Cells may carry a `data-sort` attribute holding a raw,
+/// sortable value (numbers, ISO dates); otherwise the cell text is compared.
+const SCRIPT: &str = r#"
+document.querySelectorAll("table.data").forEach(function (table) {
+  table.querySelectorAll("thead th").forEach(function (th, column) {
+    th.addEventListener("click", function () {
+      var dir = th.dataset.dir === "asc" ? "desc" : "asc";
+      table.querySelectorAll("thead th").forEach(function (h) { delete h.dataset.dir; });
+      th.dataset.dir = dir;
+      var body = table.tBodies[0];
+      var rows = Array.prototype.slice.call(body.rows);
+      var key = function (row) {
+        var cell = row.cells[column];
+        if (!cell) { return ""; }
+        var raw = cell.dataset.sort !== undefined ? cell.dataset.sort : cell.textContent;
+        var n = parseFloat(raw);
+        return (raw !== "" && !isNaN(n) && isFinite(raw)) ? n : raw.toLowerCase();
+      };
+      rows.sort(function (a, b) {
+        var x = key(a), y = key(b);
+        if (typeof x !== typeof y) { x = String(x); y = String(y); }
+        var r = x < y ? -1 : (x > y ? 1 : 0);
+        return dir === "asc" ? r : -r;
+      });
+      rows.forEach(function (row) { body.appendChild(row); });
+    });
+  });
+});
+"#;
+
+/// Renders a `MatrixOutput` as a single, self-contained HTML page (inline CSS and script, no
+/// external assets), with click-to-sort data tables.
+pub struct HtmlOutput {}
+
+impl TrMatrixOutputHandler for HtmlOutput {
+    fn output(&mut self, matrix_output: &MatrixOutput, out: &mut dyn Write) -> Result<(), JawsError> {
+        write!(out, "{}", self.render(matrix_output))?;
+        Ok(())
+    }
+}
+
+impl HtmlOutput {
+    pub(crate) fn render(&self, matrix_output: &MatrixOutput) -> String {
+        let title = matrix_output.matrix_header.as_ref()
+            .and_then(|h| h.title.clone())
+            .map(|t| format!("JAWS - {}", t.lines().next().unwrap_or_default()))
+            .unwrap_or("JAWS".to_string());
+
+        let mut body = String::new();
+        body.push_str(&self.render_header(&matrix_output.matrix_header));
+        for matrix in &matrix_output.matrices {
+            body.push_str(&self.render_matrix(matrix));
+        }
+        body.push_str(&self.render_footer(&matrix_output.matrix_footer));
+
+        format!("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+                 <title>{}</title>\n<style>{}</style>\n</head>\n<body>\n{}<script>{}</script>\n</body>\n</html>\n",
+                escape(&title), STYLE, body, SCRIPT)
+    }
+
+    fn render_header(&self, matrix_header: &Option<MatrixHeader>) -> String {
+        let mut out = String::new();
+
+        if let Some(header) = matrix_header {
+            if header.output_program_header {
+                out.push_str(&format!("<header><span>J A W S</span><span>v{}</span></header>\n",
+                                      escape(crate::VERSION)));
+            }
+            if let Some(title) = &header.title {
+                out.push_str(&format!("<h1>{}</h1>\n", lines_to_html(title)));
+            }
+        }
+
+        out
+    }
+
+    fn render_footer(&self, matrix_footer: &Option<MatrixFooter>) -> String {
+        let mut out = String::new();
+
+        if let Some(footer) = matrix_footer {
+            if let Some(text) = &footer.footer {
+                out.push_str(&format!("<p class=\"footer-text\">{}</p>\n", lines_to_html(text)));
+            }
+            if footer.output_program_footer {
+                out.push_str(&format!("<footer><span>{}</span><span>{}@{}</span></footer>\n",
+                                      Utc::now().format("%Y-%m-%dT%H:%M:%SZ"),
+                                      escape(&username()),
+                                      escape(&fallible::hostname().unwrap_or_default())));
+            }
+        }
+
+        out
+    }
+
+    fn render_matrix(&self, matrix: &Matrix) -> String {
+        let mut out = String::from("<section>\n");
+
+        if let Some(headers) = &matrix.header {
+            for header in headers {
+                out.push_str(&format!("<h2>{}</h2>\n", escape(header)));
+            }
+        }
+
+        if let Some(rows) = &matrix.rows {
+            out.push_str(&self.render_data_table(matrix, rows));
+        }
+
+        if let Some(aggregates) = &matrix.aggregate_rows {
+            out.push_str("<table class=\"aggregates\">\n<tbody>\n");
+            for aggregate in aggregates {
+                out.push_str(&format!("<tr><th>{}</th><td{}>{}</td></tr>\n",
+                                      escape(&aggregate.name),
+                                      if aggregate.value.is_numeric() { " class=\"num\"" } else { "" },
+                                      escape(&aggregate.value.to_string())));
+            }
+            out.push_str("</tbody>\n</table>\n");
+        }
+
+        if let Some(notes) = &matrix.notes {
+            out.push_str("<h3>Notes</h3>\n<ol>\n");
+            for note in notes {
+                out.push_str(&format!("<li>{}</li>\n", escape(note)));
+            }
+            out.push_str("</ol>\n");
+        }
+
+        out.push_str("</section>\n");
+        out
+    }
+
+    fn render_data_table(&self, matrix: &Matrix, rows: &[MatrixRowT]) -> String {
+        let numeric = matrix.numeric_columns();
+        let mut out = String::from("<table class=\"data\">\n");
+
+        let data = if matrix.first_rows_header && !rows.is_empty() {
+            out.push_str("<thead><tr>");
+            for cell in &rows[0] {
+                out.push_str(&format!("<th>{}</th>",
+                                      cell.as_ref().map(|c| escape(&c.to_string())).unwrap_or_default()));
+            }
+            out.push_str("</tr></thead>\n");
+            &rows[1..]
+        } else {
+            rows
+        };
+
+        out.push_str("<tbody>\n");
+        for row in data {
+            out.push_str("<tr>");
+            for (i, cell) in row.iter().enumerate() {
+                let class = if numeric.contains(&i) { " class=\"num\"" } else { "" };
+                match cell {
+                    Some(cell) => {
+                        let sort = sort_key(cell)
+                            .map(|k| format!(" data-sort=\"{}\"", escape(&k)))
+                            .unwrap_or_default();
+                        out.push_str(&format!("<td{}{}>{}</td>", class, sort, escape(&cell.to_string())));
+                    }
+                    None => out.push_str(&format!("<td{}></td>", class)),
+                }
+            }
+            out.push_str("</tr>\n");
+        }
+        out.push_str("</tbody>\n</table>\n");
+
+        out
+    }
+}
+
+/// A raw value for sorting cells whose display form doesn't sort correctly as text or as a
+/// number (money symbols and separators, durations, dates).
+fn sort_key(cell: &MatrixCell) -> Option<String> {
+    match cell {
+        MatrixCell::Money(amount, _) => amount.to_f64().map(|a| a.to_string()),
+        MatrixCell::Duration(d) => Some(d.num_seconds().to_string()),
+        MatrixCell::DateTime(dt) => Some(dt.to_rfc3339()),
+        MatrixCell::List(items) => items.first().and_then(sort_key).or(items.first().map(|i| i.to_string())),
+        _ => None,
+    }
+}
+
+fn lines_to_html(text: &str) -> String {
+    text.lines().map(escape).collect::<Vec<String>>().join("<br>")
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
@@ -1,10 +1,219 @@
+use std::io::Write;
+
+use chrono::{SecondsFormat, Utc};
+use rust_decimal::prelude::ToPrimitive;
+use serde_json::{json, Map, Value};
+use whoami::{fallible, username};
+
+use crate::errors::jaws_error::JawsError;
+use crate::matrix_handlers::e_matrix_cell::MatrixCell;
+use crate::matrix_handlers::t_matrix_output::{Matrix, MatrixFooter, MatrixHeader, MatrixOutput, MatrixRowT};
 use crate::matrix_handlers::tr_matrix_output_handler::TrMatrixOutputHandler;
-use crate::matrix_handlers::t_matrix_output::MatrixOutput;
 
+/// The version of the JSON document emitted by this handler.  Bump this whenever the shape of
+/// the document changes in a way that could break a consumer.
+pub const JSON_SCHEMA_VERSION: u32 = 2;
+
+/// Renders a `MatrixOutput` as a single JSON document.  The schema is described in the README
+/// ("JSON Output") and is, in outline:
+///
+/// ```text
+/// {
+///   "schema_version": 2,
+///   "header":   { "title": [String] | null, "program": { "name", "version" } | null } | null,
+///   "matrices": [ { "header": [String] | null, "columns": [String] | null,
+///                   "rows": [ { column: value } ] | [ [value] ],   (column names are unique)
+///                   "aggregates": [ { "name", "value" } ], "notes": [String] } ],
+///   "footer":   { "text": [String] | null, "program": { "generated_at", "user", "host" } | null } | null
+/// }
+/// ```
+///
+/// Multi-line titles, footers and headers are split into arrays of lines.  Cell values keep
+/// their type: see `cell_to_json` for the encoding of each `MatrixCell` variant.
 pub struct JsonOutput;
 
 impl TrMatrixOutputHandler for JsonOutput {
-    fn output(&mut self, _: &MatrixOutput) {
-        println!("DUMMY OUTPUT HANDLER -> Json")
+    fn output(&mut self, matrix_output: &MatrixOutput, out: &mut dyn Write) -> Result<(), JawsError> {
+        let document = self.to_json(matrix_output);
+
+        // Serializing a Value built from Strings cannot fail.
+        writeln!(out, "{}", serde_json::to_string_pretty(&document).unwrap())?;
+        Ok(())
+    }
+}
+
+impl JsonOutput {
+    pub(crate) fn to_json(&self, matrix_output: &MatrixOutput) -> Value {
+        json!({
+            "schema_version": JSON_SCHEMA_VERSION,
+            "header": self.header_to_json(&matrix_output.matrix_header),
+            "matrices": matrix_output.matrices.iter()
+                .map(|m| self.matrix_to_json(m))
+                .collect::<Vec<Value>>(),
+            "footer": self.footer_to_json(&matrix_output.matrix_footer),
+        })
+    }
+
+    fn header_to_json(&self, matrix_header: &Option<MatrixHeader>) -> Value {
+        match matrix_header {
+            None => Value::Null,
+            Some(header) => json!({
+                "title": header.title.as_ref().map(|t| lines(t)),
+                "program": if header.output_program_header {
+                    json!({ "name": "jaws", "version": crate::VERSION })
+                } else {
+                    Value::Null
+                },
+            })
+        }
+    }
+
+    fn footer_to_json(&self, matrix_footer: &Option<MatrixFooter>) -> Value {
+        match matrix_footer {
+            None => Value::Null,
+            Some(footer) => json!({
+                "text": footer.footer.as_ref().map(|t| lines(t)),
+                "program": if footer.output_program_footer {
+                    json!({
+                        "generated_at": format!("{}", Utc::now().format("%Y-%m-%dT%H:%M:%SZ")),
+                        "user": username(),
+                        "host": fallible::hostname().unwrap_or_default(),
+                    })
+                } else {
+                    Value::Null
+                },
+            })
+        }
+    }
+
+    fn matrix_to_json(&self, matrix: &Matrix) -> Value {
+        let header = matrix.header.as_ref()
+            .map(|h| h.iter().flat_map(|l| lines(l)).collect::<Vec<String>>());
+
+        let empty = Vec::new();
+        let rows = matrix.rows.as_ref().unwrap_or(&empty);
+
+        // If the first row is a header, rows become objects keyed by the column names.
+        // Otherwise they are emitted as plain arrays.
+        let (columns, json_rows) = if matrix.first_rows_header && !rows.is_empty() {
+            let columns = column_names(&rows[0]);
+            let json_rows = rows[1..].iter()
+                .map(|row| row_to_object(&columns, row))
+                .collect::<Vec<Value>>();
+            (Some(columns), json_rows)
+        } else {
+            (None, rows.iter().map(row_to_array).collect::<Vec<Value>>())
+        };
+
+        let aggregates = matrix.aggregate_rows.as_ref()
+            .map(|rows| rows.iter()
+                .map(|a| json!({ "name": a.name, "value": cell_to_json(&a.value) }))
+                .collect::<Vec<Value>>())
+            .unwrap_or_default();
+
+        json!({
+            "header": header,
+            "columns": columns,
+            "rows": json_rows,
+            "aggregates": aggregates,
+            "notes": matrix.notes.clone().unwrap_or_default(),
+        })
+    }
+}
+
+/// Derive column names from a header row.  Blank header cells get a positional name, and a
+/// name which has already been used gets a number (`Name#2`), so that every value in a row
+/// object remains addressable.
+fn column_names(header: &MatrixRowT) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for (i, cell) in header.iter().enumerate() {
+        let name = match cell {
+            Some(name) if !name.to_string().is_empty() => name.to_string(),
+            _ => positional_name(i),
+        };
+        names.push(unique_name(name, |name| names.iter().any(|n| n == name)));
+    }
+    names
+}
+
+fn positional_name(index: usize) -> String {
+    format!("column_{}", index + 1)
+}
+
+/// `name`, or if it's taken, the first of `name#2`, `name#3`... which isn't.
+fn unique_name(name: String, taken: impl Fn(&str) -> bool) -> String {
+    if !taken(&name) {
+        return name;
+    }
+    (2..).map(|n| format!("{}#{}", name, n)).find(|candidate| !taken(candidate)).unwrap()
+}
+
+fn row_to_object(columns: &[String], row: &MatrixRowT) -> Value {
+    let mut object = Map::new();
+    for (i, cell) in row.iter().enumerate() {
+        let key = match columns.get(i) {
+            Some(column) => column.clone(),
+            None => unique_name(positional_name(i), |name| columns.iter().any(|c| c == name)),
+        };
+        object.insert(key, optional_cell_to_json(cell));
+    }
+    Value::Object(object)
+}
+
+fn row_to_array(row: &MatrixRowT) -> Value {
+    Value::Array(row.iter().map(optional_cell_to_json).collect())
+}
+
+fn optional_cell_to_json(cell: &Option<MatrixCell>) -> Value {
+    match cell {
+        Some(value) => cell_to_json(value),
+        None => Value::Null,
+    }
+}
+
+/// Encode a single cell.  Numbers are emitted as JSON numbers, money as an object holding the
+/// amount (rounded to two places) and the ISO currency code, date-times as RFC 3339 strings,
+/// durations as a whole number of seconds, lists as arrays and maps as objects.
+fn cell_to_json(cell: &MatrixCell) -> Value {
+    match cell {
+        MatrixCell::String(s) => json!(s),
+        MatrixCell::Integer(i) => json!(i),
+        MatrixCell::Decimal(d) => json!(d),
+        MatrixCell::Money(amount, currency) => json!({
+            "amount": amount.round_dp(2).to_f64(),
+            "currency": currency.iso_alpha_code,
+        }),
+        MatrixCell::Bool(b) => json!(b),
+        MatrixCell::DateTime(dt) => json!(dt.to_rfc3339_opts(SecondsFormat::Secs, true)),
+        MatrixCell::Duration(d) => json!(d.num_seconds()),
+        MatrixCell::List(items) => Value::Array(items.iter().map(cell_to_json).collect()),
+        MatrixCell::Map(entries) => Value::Object(entries.iter()
+            .map(|(key, value)| (key.clone(), cell_to_json(value)))
+            .collect()),
+        MatrixCell::Unknown => json!("unknown"),
+    }
+}
+
+fn lines(text: &str) -> Vec<String> {
+    text.lines().map(|l| l.to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duplicate_column_names_are_numbered() {
+        let header: MatrixRowT = ["Name", "Region", "Name", "", "column_4", "Name#2"].iter()
+            .map(|name| Some((*name).into()))
+            .collect();
+
+        let columns = column_names(&header);
+        assert_eq!(columns, ["Name", "Region", "Name#2", "column_4", "column_4#2", "Name#2#2"]);
+
+        let row: MatrixRowT = (1..=7).map(|n| Some(MatrixCell::Integer(n))).collect();
+        assert_eq!(row_to_object(&columns, &row), json!({
+            "Name": 1, "Region": 2, "Name#2": 3, "column_4": 4, "column_4#2": 5, "Name#2#2": 6, "column_7": 7,
+        }));
     }
 }
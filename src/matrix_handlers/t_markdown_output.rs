@@ -0,0 +1,157 @@
+use std::io::Write;
+
+use chrono::Utc;
+use whoami::{fallible, username};
+
+use crate::errors::jaws_error::JawsError;
+use crate::matrix_handlers::t_matrix_output::{Matrix, MatrixFooter, MatrixHeader, MatrixOutput, MatrixRowT};
+use crate::matrix_handlers::tr_matrix_output_handler::TrMatrixOutputHandler;
+
+/// Renders a `MatrixOutput` as GitHub-flavoured Markdown, for pasting into tickets, wikis and
+/// PR descriptions.  The report title becomes a heading, each matrix a pipe table (with
+/// numeric columns right-aligned), aggregates a two-column table and notes a numbered list.
+pub struct MarkdownOutput {}
+
+impl TrMatrixOutputHandler for MarkdownOutput {
+    fn output(&mut self, matrix_output: &MatrixOutput, out: &mut dyn Write) -> Result<(), JawsError> {
+        write!(out, "{}", self.render(matrix_output))?;
+        Ok(())
+    }
+}
+
+impl MarkdownOutput {
+    pub(crate) fn render(&self, matrix_output: &MatrixOutput) -> String {
+        let mut blocks: Vec<String> = Vec::new();
+
+        if let Some(header) = self.render_header(&matrix_output.matrix_header) {
+            blocks.push(header);
+        }
+
+        for matrix in &matrix_output.matrices {
+            blocks.push(self.render_matrix(matrix));
+        }
+
+        if let Some(footer) = self.render_footer(&matrix_output.matrix_footer) {
+            blocks.push(footer);
+        }
+
+        // Blocks are separated by a blank line so that Markdown renders them separately.
+        blocks.join("\n")
+    }
+
+    fn render_header(&self, matrix_header: &Option<MatrixHeader>) -> Option<String> {
+        let header = matrix_header.as_ref()?;
+        let mut out = String::new();
+
+        if let Some(title) = &header.title {
+            for line in title.lines() {
+                out.push_str(&format!("# {}\n", line));
+            }
+        }
+        if header.output_program_header {
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            out.push_str(&format!("_JAWS v{}_\n", crate::VERSION));
+        }
+
+        if out.is_empty() { None } else { Some(out) }
+    }
+
+    fn render_footer(&self, matrix_footer: &Option<MatrixFooter>) -> Option<String> {
+        let footer = matrix_footer.as_ref()?;
+        let mut out = String::new();
+
+        if let Some(text) = &footer.footer {
+            for line in text.lines() {
+                out.push_str(&format!("{}\n", escape(line)));
+            }
+        }
+        if footer.output_program_footer {
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            out.push_str(&format!("_Generated {} by {}@{}_\n",
+                                  Utc::now().format("%Y-%m-%dT%H:%M:%SZ"),
+                                  username(),
+                                  fallible::hostname().unwrap_or_default()));
+        }
+
+        if out.is_empty() { None } else { Some(out) }
+    }
+
+    fn render_matrix(&self, matrix: &Matrix) -> String {
+        let mut out = String::new();
+
+        if let Some(headers) = &matrix.header {
+            for header in headers {
+                out.push_str(&format!("## {}\n\n", header));
+            }
+        }
+
+        if let Some(rows) = &matrix.rows {
+            out.push_str(&self.render_data_table(matrix, rows));
+        }
+
+        if let Some(aggregates) = &matrix.aggregate_rows {
+            out.push('\n');
+            out.push_str("| | |\n|---:|---|\n");
+            for aggregate in aggregates {
+                out.push_str(&format!("| **{}** | {} |\n", escape(&aggregate.name), escape(&aggregate.value.to_string())));
+            }
+        }
+
+        if let Some(notes) = &matrix.notes {
+            out.push_str("\n**Notes:**\n\n");
+            for (i, note) in notes.iter().enumerate() {
+                out.push_str(&format!("{}. {}\n", i + 1, escape(note)));
+            }
+        }
+
+        out
+    }
+
+    fn render_data_table(&self, matrix: &Matrix, rows: &[MatrixRowT]) -> String {
+        let width = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+        if width == 0 {
+            return String::new();
+        }
+
+        // GFM tables must have a header row.  If the matrix doesn't have one, it's left blank.
+        let (header, data) = if matrix.first_rows_header && !rows.is_empty() {
+            (render_row(&rows[0], width), &rows[1..])
+        } else {
+            (format!("|{}\n", " |".repeat(width)), rows)
+        };
+
+        let numeric = matrix.numeric_columns();
+        let separator: Vec<&str> = (0..width)
+            .map(|c| if numeric.contains(&c) { "---:" } else { "---" })
+            .collect();
+
+        let mut out = header;
+        out.push_str(&format!("|{}|\n", separator.join("|")));
+        for row in data {
+            out.push_str(&render_row(row, width));
+        }
+        out
+    }
+}
+
+fn render_row(row: &MatrixRowT, width: usize) -> String {
+    let cells: Vec<String> = (0..width)
+        .map(|i| match row.get(i) {
+            Some(Some(cell)) => escape(&cell.to_string()),
+            _ => String::new(),
+        })
+        .collect();
+    format!("| {} |\n", cells.join(" | "))
+}
+
+/// Escape text for use in a table cell or list item: pipes would end the cell, and line
+/// breaks would end the row.
+fn escape(text: &str) -> String {
+    text.replace('|', "\\|")
+        .replace("\r\n", "<br>")
+        .replace('\n', "<br>")
+}
@@ -0,0 +1,278 @@
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use rust_decimal::prelude::ToPrimitive;
+
+use crate::matrix_handlers::e_matrix_cell::MatrixCell;
+
+/// The comparison operators a filter can use.
+const OPERATORS: [(&str, FilterOp); 9] = [
+    ("==", FilterOp::Eq),
+    ("!=", FilterOp::Ne),
+    ("<=", FilterOp::Le),
+    (">=", FilterOp::Ge),
+    ("!~", FilterOp::NotGlob),
+    ("=", FilterOp::Eq),
+    ("<", FilterOp::Lt),
+    (">", FilterOp::Gt),
+    ("~", FilterOp::Glob),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FilterOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    /// Matches a glob pattern (`*` and `?`), case-insensitively.
+    Glob,
+    NotGlob,
+}
+
+/// A row filter, parsed from an expression such as `Status == running`, `Days < 60` or
+/// `Name ~ "web-*"`.  The left-hand side is a column name; the right-hand side is a value,
+/// optionally quoted.  Comparisons are made against the typed cell: numbers and money
+/// numerically, dates chronologically (the value may be `YYYY-MM-DD` or RFC 3339), Yes/No
+/// values as booleans and everything else as case-insensitive text.
+#[derive(Clone, Debug)]
+pub struct MatrixFilter {
+    pub column: String,
+    pub op: FilterOp,
+    pub value: String,
+}
+
+impl FromStr for MatrixFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Find the leftmost operator; at a given position, prefer the longest.
+        let mut found: Option<(usize, &str, FilterOp)> = None;
+        for (token, op) in OPERATORS.iter() {
+            if let Some(position) = s.find(token) {
+                let better = match found {
+                    None => true,
+                    Some((p, t, _)) => position < p || (position == p && token.len() > t.len()),
+                };
+                if better {
+                    found = Some((position, token, *op));
+                }
+            }
+        }
+
+        let (position, token, op) = found.ok_or_else(|| format!(
+            "'{}' has no operator; expected one of ==, !=, <, <=, >, >=, ~, !~", s))?;
+
+        let column = s[..position].trim();
+        let value = unquote(s[position + token.len()..].trim());
+
+        if column.is_empty() {
+            return Err(format!("'{}' has no column name", s));
+        }
+        if value.is_empty() && matches!(op, FilterOp::Lt | FilterOp::Le | FilterOp::Gt | FilterOp::Ge) {
+            return Err(format!("'{}' has nothing to compare with", s));
+        }
+
+        Ok(MatrixFilter { column: column.to_string(), op, value: value.to_string() })
+    }
+}
+
+impl MatrixFilter {
+    /// Test a cell against this filter.  An empty cell only matches `== ""`, `!= <anything
+    /// else>` and `!~` patterns it doesn't match.
+    pub fn matches(&self, cell: Option<&MatrixCell>) -> bool {
+        let cell = match cell {
+            Some(cell) => cell,
+            None => return match self.op {
+                FilterOp::Eq => self.value.is_empty(),
+                FilterOp::Ne => !self.value.is_empty(),
+                FilterOp::Glob => glob_match(&self.value, ""),
+                FilterOp::NotGlob => !glob_match(&self.value, ""),
+                _ => false,
+            },
+        };
+
+        match self.op {
+            FilterOp::Glob => glob_match(&self.value, &cell.to_string()),
+            FilterOp::NotGlob => !glob_match(&self.value, &cell.to_string()),
+            _ => match self.compare(cell) {
+                None => false,
+                Some(ordering) => match self.op {
+                    FilterOp::Eq => ordering == Ordering::Equal,
+                    FilterOp::Ne => ordering != Ordering::Equal,
+                    FilterOp::Lt => ordering == Ordering::Less,
+                    FilterOp::Le => ordering != Ordering::Greater,
+                    FilterOp::Gt => ordering == Ordering::Greater,
+                    FilterOp::Ge => ordering != Ordering::Less,
+                    FilterOp::Glob | FilterOp::NotGlob => unreachable!(),
+                },
+            },
+        }
+    }
+
+    /// Compare the cell with the filter value, interpreting the value according to the
+    /// cell's type.  Returns None if the value can't be interpreted that way, in which case
+    /// the row doesn't match (except for `!=`, where the values are certainly different).
+    fn compare(&self, cell: &MatrixCell) -> Option<Ordering> {
+        let ordering = match cell {
+            MatrixCell::Integer(_) | MatrixCell::Decimal(_) | MatrixCell::Money(_, _) | MatrixCell::Duration(_) => {
+                let number = cell_number(cell)?;
+                parse_number(&self.value).map(|value| number.total_cmp(&value))
+            }
+            MatrixCell::DateTime(dt) => parse_date(&self.value).map(|value| dt.cmp(&value)),
+            MatrixCell::Bool(b) => parse_bool(&self.value).map(|value| b.cmp(&value)),
+            _ => Some(cell.to_string().to_lowercase().cmp(&self.value.to_lowercase())),
+        };
+
+        match (ordering, self.op) {
+            (None, FilterOp::Ne) => Some(Ordering::Less),
+            (ordering, _) => ordering,
+        }
+    }
+}
+
+fn cell_number(cell: &MatrixCell) -> Option<f64> {
+    match cell {
+        MatrixCell::Integer(i) => Some(*i as f64),
+        MatrixCell::Decimal(d) => Some(*d),
+        MatrixCell::Money(amount, _) => amount.to_f64(),
+        MatrixCell::Duration(d) => Some(d.num_seconds() as f64),
+        _ => None,
+    }
+}
+
+/// Parse a number, allowing the currency symbols and thousands separators that appear in
+/// rendered money values (e.g. `$1,234.50`).
+fn parse_number(value: &str) -> Option<f64> {
+    value.chars()
+        .filter(|c| !matches!(c, '$' | '€' | '£' | ',' | ' '))
+        .collect::<String>()
+        .parse::<f64>()
+        .ok()
+}
+
+fn parse_date(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(value) {
+        return Some(dt.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .map(|d| d.and_utc())
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "yes" | "true" | "y" | "1" => Some(true),
+        "no" | "false" | "n" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+fn unquote(value: &str) -> &str {
+    for quote in ['"', '\''] {
+        if value.len() >= 2 && value.starts_with(quote) && value.ends_with(quote) {
+            return &value[1..value.len() - 1];
+        }
+    }
+    value
+}
+
+/// Case-insensitive glob matching, supporting `*` (any run of characters) and `?` (any single
+/// character).
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            // Let the last star absorb one more character and try again.
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn filter(s: &str) -> MatrixFilter {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_the_leftmost_longest_operator() {
+        let parsed = filter("Days <= 60");
+        assert_eq!((parsed.column.as_str(), parsed.op, parsed.value.as_str()), ("Days", FilterOp::Le, "60"));
+
+        let parsed = filter("Name ~ 'web-*'");
+        assert_eq!((parsed.column.as_str(), parsed.op, parsed.value.as_str()), ("Name", FilterOp::Glob, "web-*"));
+
+        // The value may itself contain operators.
+        let parsed = filter("Tags = \"a=b\"");
+        assert_eq!((parsed.column.as_str(), parsed.op, parsed.value.as_str()), ("Tags", FilterOp::Eq, "a=b"));
+        assert_eq!(filter("Name !~ db*").op, FilterOp::NotGlob);
+        assert_eq!(filter("Status != running").op, FilterOp::Ne);
+    }
+
+    #[test]
+    fn rejects_malformed_expressions() {
+        assert!("Status running".parse::<MatrixFilter>().unwrap_err().contains("no operator"));
+        assert!("== running".parse::<MatrixFilter>().unwrap_err().contains("no column name"));
+        assert!("Days <".parse::<MatrixFilter>().unwrap_err().contains("nothing to compare with"));
+        assert!("Name ==".parse::<MatrixFilter>().is_ok());
+    }
+
+    #[test]
+    fn compares_typed_cells() {
+        assert!(filter("Days < 60").matches(Some(&MatrixCell::Integer(59))));
+        assert!(!filter("Days < 60").matches(Some(&MatrixCell::Integer(60))));
+        assert!(filter("$ Res/Year > $1,000").matches(Some(&MatrixCell::usd(1000.5))));
+        assert!(filter("Spot == no").matches(Some(&MatrixCell::Bool(false))));
+        assert!(filter("Status == RUNNING").matches(Some(&MatrixCell::String("running".to_string()))));
+
+        let expiry = MatrixCell::DateTime(Utc.with_ymd_and_hms(2027, 3, 1, 12, 0, 0).unwrap());
+        assert!(filter("Expiry >= 2027-03-01").matches(Some(&expiry)));
+        assert!(filter("Expiry < 2027-03-01T13:00:00Z").matches(Some(&expiry)));
+
+        // A value which can't be read as the cell's type matches nothing but !=.
+        assert!(!filter("Days < soon").matches(Some(&MatrixCell::Integer(1))));
+        assert!(filter("Days != soon").matches(Some(&MatrixCell::Integer(1))));
+    }
+
+    #[test]
+    fn empty_cells_match_only_empty_values() {
+        assert!(filter("Public IP == ''").matches(None));
+        assert!(!filter("Public IP == 1.2.3.4").matches(None));
+        assert!(filter("Public IP != 1.2.3.4").matches(None));
+        assert!(filter("Public IP !~ 203.*").matches(None));
+        assert!(!filter("Days < 60").matches(None));
+    }
+
+    #[test]
+    fn globs_ignore_case() {
+        assert!(glob_match("web-*", "WEB-1"));
+        assert!(glob_match("web-?", "web-2"));
+        assert!(glob_match("*-db-*", "prod-db-primary"));
+        assert!(!glob_match("web-?", "web-10"));
+        assert!(!glob_match("db", "db-1"));
+    }
+}
@@ -0,0 +1,141 @@
+use crate::matrix_handlers::t_matrix_output::{add_note, Matrix, MatrixAggregateFn, MatrixAggregateValue, MatrixOutput,
+                                              MatrixRowT};
+
+/// The output of one run of a command, and the values which say where it came from, e.g.
+/// `[("Region", "eu-west-1")]`.
+pub struct LabelledOutput {
+    pub labels: Vec<(String, String)>,
+    pub output: MatrixOutput,
+}
+
+/// Merge the outputs of several runs of the same command into one.  Matrices are matched by
+/// their title (or position, if they have none), and each gets a column for every label,
+/// first.  Each aggregate is totalled over all the runs, followed by its value for each run
+/// when more than one contributed.  Notes are prefixed with the run's labels.
+pub fn merge_outputs(outputs: Vec<LabelledOutput>) -> Option<MatrixOutput> {
+    let mut merged: Option<MatrixOutput> = None;
+    let mut merges: Vec<MatrixMerge> = Vec::new();
+
+    for LabelledOutput { labels, output } in outputs {
+        // Every run has the same header and footer.
+        merged.get_or_insert(MatrixOutput {
+            matrix_header: output.matrix_header,
+            matrix_footer: output.matrix_footer,
+            matrices: Vec::new(),
+        });
+
+        for (position, matrix) in output.matrices.into_iter().enumerate() {
+            let key = matrix_key(&matrix, position);
+            match merges.iter_mut().find(|m| m.key == key) {
+                Some(merge) => merge.add(&labels, matrix),
+                None => merges.push(MatrixMerge::new(key, &labels, matrix)),
+            }
+        }
+    }
+
+    let mut merged = merged?;
+    merged.matrices = merges.into_iter().map(MatrixMerge::finish).collect();
+    Some(merged)
+}
+
+/// A merged matrix in the making.
+struct MatrixMerge {
+    key: String,
+    matrix: Matrix,
+    /// The aggregates over every run, in the order they were first seen.
+    totals: Vec<MatrixAggregateValue>,
+    /// Each run's aggregates.
+    breakdown: Vec<MatrixAggregateValue>,
+    runs: usize,
+}
+
+impl MatrixMerge {
+    fn new(key: String, labels: &[(String, String)], mut matrix: Matrix) -> Self {
+        // The header row is kept, with a column for each label first; `add` takes the rest.
+        let mut rows: Vec<MatrixRowT> = Vec::new();
+        if matrix.first_rows_header {
+            if let Some(mut header) = matrix.rows.as_mut().filter(|r| !r.is_empty()).map(|r| r.remove(0)) {
+                for (n, (column, _)) in labels.iter().enumerate() {
+                    header.insert(n, Some(column.as_str().into()));
+                }
+                rows.push(header);
+            }
+        }
+
+        let mut merge = Self {
+            key,
+            matrix: Matrix {
+                header: matrix.header.take(),
+                rows: Some(rows),
+                aggregate_rows: None,
+                notes: None,
+                first_rows_header: matrix.first_rows_header,
+            },
+            totals: Vec::new(),
+            breakdown: Vec::new(),
+            runs: 0,
+        };
+
+        matrix.first_rows_header = false;
+        merge.add(labels, matrix);
+        merge
+    }
+
+    fn add(&mut self, labels: &[(String, String)], matrix: Matrix) {
+        self.runs += 1;
+        let skip = if matrix.first_rows_header { 1 } else { 0 };
+
+        let rows = self.matrix.rows.get_or_insert_with(Vec::new);
+        for row in matrix.rows.into_iter().flatten().skip(skip) {
+            let mut labelled: MatrixRowT = labels.iter().map(|(_, value)| Some(value.as_str().into())).collect();
+            labelled.extend(row);
+            rows.push(labelled);
+        }
+
+        let label = labels.iter()
+            .map(|(_, value)| value.as_str())
+            .filter(|value| !value.is_empty())
+            .collect::<Vec<_>>()
+            .join(" / ");
+
+        for aggregate in matrix.aggregate_rows.into_iter().flatten() {
+            match self.totals.iter_mut().find(|t| t.name == aggregate.name) {
+                Some(total) => total.value = total.value.clone().add(&aggregate.value),
+                None => self.totals.push(MatrixAggregateValue {
+                    name: aggregate.name.clone(),
+                    value: aggregate.value.clone(),
+                    function: aggregate.function.clone(),
+                }),
+            }
+
+            self.breakdown.push(MatrixAggregateValue {
+                name: format!("{} ({})", aggregate.name, label),
+                value: aggregate.value,
+                function: aggregate.function.map(|f| MatrixAggregateFn::Within(labels.to_vec(), Box::new(f))),
+            });
+        }
+
+        for note in matrix.notes.into_iter().flatten() {
+            add_note(self.matrix.notes.get_or_insert_with(Vec::new), format!("{}: {}", label, note));
+        }
+    }
+
+    fn finish(mut self) -> Matrix {
+        if !self.totals.is_empty() {
+            if self.runs > 1 {
+                self.totals.append(&mut self.breakdown);
+            }
+            self.matrix.aggregate_rows = Some(self.totals);
+        }
+        self.matrix
+    }
+}
+
+/// Identifies matching matrices in different runs: the first line of the title, or failing
+/// that the position.
+fn matrix_key(matrix: &Matrix, position: usize) -> String {
+    matrix.header.as_ref()
+        .and_then(|h| h.first())
+        .cloned()
+        .unwrap_or_else(|| format!("#{}", position + 1))
+}
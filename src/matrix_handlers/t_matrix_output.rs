@@ -1,7 +1,8 @@
-use std::fmt::Display;
+use std::cmp::Ordering;
 
-/// Defines the output of a Matrix-capable command.
+use crate::matrix_handlers::e_matrix_cell::MatrixCell;
 
+/// Defines the output of a Matrix-capable command.
 pub struct MatrixOutput {
     pub matrix_header: Option<MatrixHeader>,
     pub matrix_footer: Option<MatrixFooter>,
@@ -30,8 +31,7 @@ pub struct Matrix {
     /// May contain line breaks; some output formats may render these as an array.
     pub header: Option<Vec<String>>,
 
-    /// Row data can be any type that implements Display, hence it needs to be Boxed, since
-    /// Display is a trait and the size of the underlying object cannot be known at compile-time.
+    /// Row data is typed (see `MatrixCell`); each output handler decides how to render it.
     /// Jagged rows are not allowed.  If a cell is not filled, the Option should be None.
     pub rows: Option<MatrixRowsT>,
 
@@ -48,16 +48,189 @@ pub struct Matrix {
     pub first_rows_header: bool,
 }
 
+impl Matrix {
+    /// The column names, if the first row is a header.  Blank header cells yield empty names.
+    pub fn column_names(&self) -> Option<Vec<String>> {
+        if !self.first_rows_header {
+            return None;
+        }
+
+        self.rows.as_ref()
+            .and_then(|rows| rows.first())
+            .map(|header| header.iter()
+                .map(|c| c.as_ref().map(|c| c.to_string()).unwrap_or_default())
+                .collect())
+    }
+
+    /// Find a column by header name (case-insensitive, ignoring surrounding whitespace).
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.column_names()?
+            .iter()
+            .position(|c| c.trim().eq_ignore_ascii_case(name.trim()))
+    }
+
+    /// Sort the data rows (leaving any header row in place) by the given keys, each a column
+    /// index and whether it is descending.  Empty cells always sort last.  The sort is stable,
+    /// so rows which compare equal keep the order the command produced.
+    pub fn sort_rows(&mut self, keys: &[(usize, bool)]) {
+        let skip = if self.first_rows_header { 1 } else { 0 };
+
+        if let Some(rows) = self.rows.as_mut() {
+            if rows.len() <= skip {
+                return;
+            }
+
+            rows[skip..].sort_by(|a, b| {
+                for &(column, descending) in keys {
+                    let ordering = match (a.get(column).and_then(|c| c.as_ref()),
+                                          b.get(column).and_then(|c| c.as_ref())) {
+                        (Some(x), Some(y)) if descending => y.sort_cmp(x),
+                        (Some(x), Some(y)) => x.sort_cmp(y),
+                        (Some(_), None) => Ordering::Less,
+                        (None, Some(_)) => Ordering::Greater,
+                        (None, None) => Ordering::Equal,
+                    };
+                    if ordering != Ordering::Equal {
+                        return ordering;
+                    }
+                }
+                Ordering::Equal
+            });
+        }
+    }
+
+    /// Keep only the data rows for which `keep` returns true.  Any header row is kept.
+    pub fn retain_rows<F: Fn(&MatrixRowT) -> bool>(&mut self, keep: F) {
+        let skip = if self.first_rows_header { 1 } else { 0 };
+
+        if let Some(rows) = self.rows.as_mut() {
+            let mut index = 0;
+            rows.retain(|row| {
+                index += 1;
+                index <= skip || keep(row)
+            });
+        }
+    }
+
+    /// Recompute every aggregate which has a function from the current data rows.  If the
+    /// column an aggregate refers to has gone, its value is left alone.
+    pub fn recompute_aggregates(&mut self) {
+        let skip = if self.first_rows_header { 1 } else { 0 };
+        let data: Vec<&MatrixRowT> = self.rows.iter().flatten().skip(skip).collect();
+
+        let mut values: Vec<Option<MatrixCell>> = Vec::new();
+        for aggregate in self.aggregate_rows.iter().flatten() {
+            values.push(aggregate.function.as_ref()
+                .and_then(|f| self.compute_aggregate(f, &aggregate.value, &data)));
+        }
+
+        for (aggregate, value) in self.aggregate_rows.iter_mut().flatten().zip(values) {
+            if let Some(value) = value {
+                aggregate.value = value;
+            }
+        }
+    }
+
+    fn compute_aggregate(&self, function: &MatrixAggregateFn, current: &MatrixCell, data: &[&MatrixRowT])
+                         -> Option<MatrixCell> {
+        let (column, element) = match function {
+            MatrixAggregateFn::Count => return Some(MatrixCell::Integer(data.len() as i64)),
+            MatrixAggregateFn::Sum(column) => (self.column_index(column)?, None),
+            MatrixAggregateFn::SumElement(column, element) => (self.column_index(column)?, Some(*element)),
+            MatrixAggregateFn::Within(labels, function) => {
+                let mut rows: Vec<&MatrixRowT> = data.to_vec();
+                for (column, value) in labels {
+                    let column = self.column_index(column)?;
+                    rows.retain(|row| row.get(column)
+                        .and_then(|c| c.as_ref())
+                        .is_some_and(|c| c.to_string() == *value));
+                }
+                return self.compute_aggregate(function, current, &rows);
+            }
+        };
+
+        let cells = data.iter()
+            .filter_map(|row| row.get(column).and_then(|c| c.as_ref()))
+            .filter_map(|cell| match (element, cell) {
+                (Some(e), MatrixCell::List(items)) => items.get(e),
+                (Some(_), _) => None,
+                (None, cell) => Some(cell),
+            });
+
+        // The sum starts from the zero of the current value's type, so that e.g. an empty
+        // money column still sums to money.
+        Some(cells.fold(current.zero(), |total, cell| total.add(cell)))
+    }
+
+    /// Reduce the matrix to the given columns, in the given order.  Each entry is an index
+    /// into the current columns.  Rows shorter than an index get an empty cell.
+    pub fn select_columns(&mut self, indices: &[usize]) {
+        if let Some(rows) = self.rows.as_mut() {
+            for row in rows.iter_mut() {
+                *row = indices.iter()
+                    .map(|&i| row.get(i).cloned().flatten())
+                    .collect();
+            }
+        }
+    }
+
+    /// Returns the indices of the columns whose data cells are all numeric.  Header rows and
+    /// empty cells are ignored; a column with no data at all is not considered numeric.
+    pub fn numeric_columns(&self) -> Vec<usize> {
+        let rows = match &self.rows {
+            Some(rows) => rows,
+            None => return Vec::new(),
+        };
+
+        let skip = if self.first_rows_header { 1 } else { 0 };
+        let data: Vec<_> = rows.iter().skip(skip).collect();
+        let width = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+
+        (0..width)
+            .filter(|&column| {
+                let cells: Vec<_> = data.iter().filter_map(|row| row.get(column).and_then(|c| c.as_ref())).collect();
+                !cells.is_empty() && cells.iter().all(|c| c.is_numeric())
+            })
+            .collect()
+    }
+}
+
 /// An aggregation of values found in a Matrix (e.g. Total, Average etc.).
 pub struct MatrixAggregateValue {
     /// The name of this aggregate
     pub name: String,
     /// The value of the aggregate.  This must have a value.
-    pub value: Box<dyn Display>,
+    pub value: MatrixCell,
+    /// How the value is computed from the matrix's rows, if it can be.  Aggregates with a
+    /// function are recomputed when the rows change (e.g. when they are filtered); others
+    /// are left as the command produced them.
+    pub function: Option<MatrixAggregateFn>,
+}
+
+/// A computation over a matrix's data rows, used to recompute aggregates.
+#[derive(Clone, Debug)]
+pub enum MatrixAggregateFn {
+    /// The number of data rows.
+    Count,
+    /// The sum of a column, by header name.
+    Sum(String),
+    /// The sum of one element of a list column, e.g. the vCPUs (element 0) of a Spec column.
+    SumElement(String, usize),
+    /// Another computation over just the rows whose columns have the given values, e.g. the
+    /// rows with "eu-west-1" in the Region column.
+    Within(Vec<(String, String)>, Box<MatrixAggregateFn>),
+}
+
+/// Add a note for a matrix, unless an identical one is already present.  The same failure
+/// often affects many rows, and it only needs explaining once.
+pub fn add_note(notes: &mut Vec<String>, note: String) {
+    if !notes.contains(&note) {
+        notes.push(note);
+    }
 }
 
 // Not sure whether these type aliases help or hinder the client code.
 
 pub type MatrixRowsT = Vec<MatrixRowT>;
-pub type MatrixRowT = Vec<Option<Box<dyn Display>>>;
+pub type MatrixRowT = Vec<Option<MatrixCell>>;
 pub type MatrixAggregateRowT = Vec<MatrixAggregateValue>;
@@ -1,23 +1,107 @@
+use std::fs::File;
+use std::io::{stdout, BufWriter, Write};
+use std::path::PathBuf;
+
 use crate::e_output_format::OutputFormat;
+use crate::e_table_style::TableStyle;
+use crate::errors::jaws_error::{JawsError, JawsErrorKind};
+use crate::matrix_handlers::t_delimited_output::DelimitedOutput;
+use crate::matrix_handlers::t_html_output::HtmlOutput;
 use crate::matrix_handlers::t_json_output::JsonOutput;
+use crate::matrix_handlers::t_markdown_output::MarkdownOutput;
 use crate::matrix_handlers::t_matrix_output::MatrixOutput;
 use crate::matrix_handlers::t_tabular_output::TabularOutput;
 use crate::matrix_handlers::tr_matrix_output_handler::TrMatrixOutputHandler;
+use crate::t_output_target::OutputTarget;
 
 pub struct MatrixOutputDriver {
-    pub(crate) output_format: OutputFormat,
+    /// Each target receives the whole output, in its own format.
+    pub(crate) targets: Vec<OutputTarget>,
     pub(crate) matrix_output: MatrixOutput,
+    /// If set, only the matrix matching this selector is output (see `select_matrix`).
+    pub(crate) matrix_selector: Option<String>,
+    /// If set, delimited formats write each matrix to its own file in this directory.
+    pub(crate) split_dir: Option<PathBuf>,
+    /// The AWS profile in use, shown in the tabular program footer.
+    pub(crate) profile: Option<String>,
+    /// The border style of tabular output.
+    pub(crate) table_style: TableStyle,
 }
 
 
 impl MatrixOutputDriver {
-    pub fn output(&mut self) {
+    pub fn output(&mut self) -> Result<(), JawsError> {
+        if let Some(selector) = &self.matrix_selector {
+            select_matrix(&mut self.matrix_output, selector)?;
+        }
+
+        for target in &self.targets {
+            self.output_target(target)?;
+        }
+
+        Ok(())
+    }
+
+    fn output_target(&self, target: &OutputTarget) -> Result<(), JawsError> {
         let mut formatter: Box<dyn TrMatrixOutputHandler> =
-            match self.output_format {
-                OutputFormat::Tabular => Box::new(TabularOutput {}) as Box<dyn TrMatrixOutputHandler>,
-                OutputFormat::Json => Box::new(JsonOutput {}) as Box<dyn TrMatrixOutputHandler>
+            match target.format {
+                OutputFormat::Tabular => Box::new(TabularOutput {
+                    styled: target.is_stdout(),
+                    profile: self.profile.clone(),
+                    table_style: self.table_style,
+                }) as Box<dyn TrMatrixOutputHandler>,
+                OutputFormat::Json => Box::new(JsonOutput {}) as Box<dyn TrMatrixOutputHandler>,
+                OutputFormat::Csv => Box::new(DelimitedOutput::csv(self.split_dir.clone())),
+                OutputFormat::Tsv => Box::new(DelimitedOutput::tsv(self.split_dir.clone())),
+                OutputFormat::Markdown => Box::new(MarkdownOutput {}),
+                OutputFormat::Html => Box::new(HtmlOutput {}),
             };
 
-        formatter.output(&self.matrix_output);
+        match &target.file {
+            None => {
+                let mut out = stdout().lock();
+                formatter.output(&self.matrix_output, &mut out)?;
+                out.flush()?;
+            }
+            Some(path) => {
+                let file = File::create(path).map_err(|e| JawsError::with_kind(JawsErrorKind::Output,
+                    format!("Couldn't create '{}': {}", path.display(), e)).with_source(e))?;
+                let mut out = BufWriter::new(file);
+                formatter.output(&self.matrix_output, &mut out)?;
+                out.flush()?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Reduce the output to a single matrix.  The selector is either a 1-based position, or the
+/// (case-insensitive) first line of a matrix's header, e.g. "Uncovered Instances".
+fn select_matrix(matrix_output: &mut MatrixOutput, selector: &str) -> Result<(), JawsError> {
+    let position = match selector.trim().parse::<usize>() {
+        Ok(n) if n >= 1 && n <= matrix_output.matrices.len() => Some(n - 1),
+        Ok(_) => None,
+        Err(_) => matrix_output.matrices.iter().position(|m| {
+            m.header.as_ref()
+                .and_then(|h| h.first())
+                .is_some_and(|h| h.eq_ignore_ascii_case(selector.trim()))
+        }),
+    };
+
+    match position {
+        Some(i) => {
+            let matrix = matrix_output.matrices.swap_remove(i);
+            matrix_output.matrices = vec![matrix];
+            Ok(())
+        }
+        None => {
+            let available: Vec<String> = matrix_output.matrices.iter().enumerate()
+                .map(|(i, m)| format!("  {}: {}", i + 1,
+                                      m.header.as_ref().and_then(|h| h.first()).cloned().unwrap_or_default()))
+                .collect();
+            Err(JawsError::with_kind(JawsErrorKind::Usage, format!("No table matches '{}'.  Available tables are:\n{}",
+                                       selector, available.join("\n"))))
+        }
     }
 }
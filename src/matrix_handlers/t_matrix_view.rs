@@ -0,0 +1,187 @@
+use std::str::FromStr;
+
+use crate::errors::jaws_error::{JawsError, JawsErrorKind};
+use crate::matrix_handlers::t_matrix_filter::MatrixFilter;
+use crate::matrix_handlers::t_matrix_output::MatrixOutput;
+use crate::Options;
+
+/// Reshapes a command's `MatrixOutput` according to the user's global view options before it
+/// reaches the output driver.  This works for any matrix whose first row is a header, so
+/// commands don't need to implement these options themselves.
+pub struct MatrixView {
+    /// Row filters, all of which a row must match to be kept.
+    pub filters: Vec<MatrixFilter>,
+    /// The columns to show, in order, by header name.  Empty means all columns.
+    pub columns: Vec<String>,
+    /// Sort keys, most significant first.  Empty means the command's own order.
+    pub sort_by: Vec<SortKey>,
+}
+
+/// A column to sort by, parsed from `COLUMN`, `COLUMN:asc` or `COLUMN:desc`.
+#[derive(Clone, Debug)]
+pub struct SortKey {
+    pub column: String,
+    pub descending: bool,
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (column, descending) = match s.rsplit_once(':') {
+            Some((column, direction)) => match direction.trim().to_lowercase().as_str() {
+                "asc" => (column, false),
+                "desc" => (column, true),
+                _ => return Err(format!("sort direction must be 'asc' or 'desc', not '{}'", direction)),
+            },
+            None => (s, false),
+        };
+
+        if column.trim().is_empty() {
+            return Err("a column name is required".to_string());
+        }
+
+        Ok(SortKey { column: column.trim().to_string(), descending })
+    }
+}
+
+impl MatrixView {
+    pub fn new(options: &Options) -> Self {
+        Self {
+            filters: options.where_.clone(),
+            columns: options.columns.iter()
+                .map(|c| c.trim().to_string())
+                .filter(|c| !c.is_empty())
+                .collect(),
+            sort_by: options.sort_by.clone(),
+        }
+    }
+
+    pub fn apply(&self, matrix_output: &mut MatrixOutput) -> Result<(), JawsError> {
+        // Filtering happens first, so that rows can be filtered by a column which isn't shown,
+        // and so that there is less to sort.
+        if !self.filters.is_empty() {
+            self.apply_filters(matrix_output)?;
+        }
+
+        if !self.sort_by.is_empty() {
+            self.apply_sort(matrix_output)?;
+        }
+
+        if !self.columns.is_empty() {
+            self.apply_columns(matrix_output)?;
+        }
+
+        Ok(())
+    }
+
+    /// Keep only the rows which match every filter on a column the matrix has, and recompute
+    /// the matrix's aggregates over the rows that remain.  Every filtered column must exist in
+    /// at least one matrix.
+    fn apply_filters(&self, matrix_output: &mut MatrixOutput) -> Result<(), JawsError> {
+        let mut found = vec![false; self.filters.len()];
+
+        for matrix in matrix_output.matrices.iter_mut() {
+            let mut filters: Vec<(usize, &MatrixFilter)> = Vec::new();
+            for (n, filter) in self.filters.iter().enumerate() {
+                if let Some(i) = matrix.column_index(&filter.column) {
+                    filters.push((i, filter));
+                    found[n] = true;
+                }
+            }
+
+            if !filters.is_empty() {
+                matrix.retain_rows(|row| filters.iter()
+                    .all(|(i, filter)| filter.matches(row.get(*i).and_then(|c| c.as_ref()))));
+                matrix.recompute_aggregates();
+            }
+        }
+
+        let missing: Vec<&String> = self.filters.iter().zip(found)
+            .filter(|(_, found)| !found)
+            .map(|(filter, _)| &filter.column)
+            .collect();
+
+        check_missing(matrix_output, &missing)
+    }
+
+    /// Sort each matrix by those of the sort keys it has.  As with columns, every key must
+    /// exist in at least one matrix.
+    fn apply_sort(&self, matrix_output: &mut MatrixOutput) -> Result<(), JawsError> {
+        let mut found = vec![false; self.sort_by.len()];
+
+        for matrix in matrix_output.matrices.iter_mut() {
+            let mut keys: Vec<(usize, bool)> = Vec::new();
+            for (n, key) in self.sort_by.iter().enumerate() {
+                if let Some(i) = matrix.column_index(&key.column) {
+                    keys.push((i, key.descending));
+                    found[n] = true;
+                }
+            }
+
+            if !keys.is_empty() {
+                matrix.sort_rows(&keys);
+            }
+        }
+
+        let missing: Vec<&String> = self.sort_by.iter().zip(found)
+            .filter(|(_, found)| !found)
+            .map(|(key, _)| &key.column)
+            .collect();
+
+        check_missing(matrix_output, &missing)
+    }
+
+    /// Select and reorder columns.  A matrix which has none of the requested columns (e.g. the
+    /// reservations table when selecting instance columns in `res`) is left as it is, but
+    /// every requested column must exist in at least one matrix.
+    fn apply_columns(&self, matrix_output: &mut MatrixOutput) -> Result<(), JawsError> {
+        let mut found = vec![false; self.columns.len()];
+
+        for matrix in matrix_output.matrices.iter_mut() {
+            let mut indices: Vec<usize> = Vec::new();
+            for (n, name) in self.columns.iter().enumerate() {
+                if let Some(i) = matrix.column_index(name) {
+                    indices.push(i);
+                    found[n] = true;
+                }
+            }
+
+            if !indices.is_empty() {
+                matrix.select_columns(&indices);
+            }
+        }
+
+        let missing: Vec<&String> = self.columns.iter().zip(found)
+            .filter(|(_, found)| !found)
+            .map(|(name, _)| name)
+            .collect();
+
+        check_missing(matrix_output, &missing)
+    }
+}
+
+/// Fail with a helpful message if any of the named columns weren't found.
+fn check_missing(matrix_output: &MatrixOutput, missing: &[&String]) -> Result<(), JawsError> {
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(JawsError::with_kind(JawsErrorKind::Usage, format!(
+            "Unknown column(s): {}.\n\nAvailable columns are: {}",
+            missing.iter().map(|m| format!("'{}'", m)).collect::<Vec<String>>().join(", "),
+            available_columns(matrix_output).join(", "))))
+    }
+}
+
+/// All distinct column names across the output, in order of first appearance.
+fn available_columns(matrix_output: &MatrixOutput) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for matrix in &matrix_output.matrices {
+        for name in matrix.column_names().unwrap_or_default() {
+            if !name.is_empty() && !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    names
+}
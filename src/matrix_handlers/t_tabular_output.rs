@@ -1,9 +1,15 @@
+use crate::e_table_style::TableStyle;
+use crate::errors::jaws_error::JawsError;
 use crate::matrix_handlers::t_matrix_output::{Matrix, MatrixFooter, MatrixHeader, MatrixOutput};
 use crate::matrix_handlers::tr_matrix_output_handler::TrMatrixOutputHandler;
 use crate::textutils::get_terminal_size;
 use crate::tui::{tui_center_text, tui_lcr_text, tui_separator_bar};
 use chrono::Utc;
+use std::fmt::Display;
+use std::io;
+use std::io::Write;
 use tabled::builder::Builder;
+use tabled::Table;
 use tabled::settings::object::Columns;
 use tabled::settings::peaker::PriorityMax;
 use tabled::settings::themes::ColumnNames;
@@ -11,73 +17,102 @@ use tabled::settings::width::{MinWidth, Wrap};
 use tabled::settings::{Alignment, Settings, Style, Width};
 use whoami::{fallible, username};
 
-pub struct TabularOutput {}
+pub struct TabularOutput {
+    /// If true, terminal styling (bold, underline) is emitted.  This should be false when
+    /// writing to a file.
+    pub styled: bool,
+    /// The AWS profile in use, if one was named.
+    pub profile: Option<String>,
+    /// The border style of every table.
+    pub table_style: TableStyle,
+}
 
 impl TrMatrixOutputHandler for TabularOutput {
-    fn output(&mut self, matrix_output: &MatrixOutput) {
-        self.output_header(&matrix_output.matrix_header);
-        println!();
-        
+    fn output(&mut self, matrix_output: &MatrixOutput, out: &mut dyn Write) -> Result<(), JawsError> {
+        self.output_header(out, &matrix_output.matrix_header)?;
+        writeln!(out)?;
+
         for matrix in &matrix_output.matrices {
-            self.output_matrix(&matrix);
+            self.output_matrix(out, matrix)?;
         }
-        println!();
-        self.output_footer(&matrix_output.matrix_footer);
+        writeln!(out)?;
+        self.output_footer(out, &matrix_output.matrix_footer)?;
+        Ok(())
     }
 }
 
 impl TabularOutput {
-    fn output_header(&self, matrix_header: &Option<MatrixHeader>) {
+    fn style(&self, style: impl Display) -> String {
+        if self.styled { style.to_string() } else { String::new() }
+    }
+
+    /// Draw the table's borders in the chosen style.  Only the modern style has lines between
+    /// rows.
+    fn apply_table_style(&self, table: &mut Table) {
+        match self.table_style {
+            TableStyle::Rounded => table.with(Style::rounded().remove_horizontals()),
+            TableStyle::Sharp => table.with(Style::sharp().remove_horizontals()),
+            TableStyle::Modern => table.with(Style::modern()),
+            TableStyle::Ascii => table.with(Style::ascii().remove_horizontal()),
+            TableStyle::Dots => table.with(Style::dots().remove_horizontal()),
+        };
+    }
+
+    fn output_header(&self, out: &mut dyn Write, matrix_header: &Option<MatrixHeader>) -> io::Result<()> {
         // Program header
         if matrix_header.is_some() {
-            println!("{}", termion::style::Bold);
+            writeln!(out, "{}", self.style(termion::style::Bold))?;
             if matrix_header.as_ref().unwrap().output_program_header {
-                println!("{}", tui_lcr_text(
+                writeln!(out, "{}", tui_lcr_text(
                     Some("J A W S".to_string()),
                     Some("*".repeat(5)),
                     Some(format!("v{}", crate::VERSION).to_string()),
-                ))
+                ))?;
             }
 
-            println!("{}", tui_separator_bar("-"));
+            writeln!(out, "{}", tui_separator_bar("-"))?;
 
-            println!("{}", termion::style::Reset);
+            writeln!(out, "{}", self.style(termion::style::Reset))?;
 
             // Matrix header if present
             if matrix_header.as_ref().unwrap().title.is_some() {
-                println!("{}", tui_center_text(
-                    matrix_header.as_ref().unwrap().title.as_ref().unwrap()));
+                writeln!(out, "{}", tui_center_text(
+                    matrix_header.as_ref().unwrap().title.as_ref().unwrap()))?;
             }
         }
+        Ok(())
     }
 
-    pub(crate) fn output_footer(&self, matrix_footer: &Option<MatrixFooter>) {
+    pub(crate) fn output_footer(&self, out: &mut dyn Write, matrix_footer: &Option<MatrixFooter>) -> io::Result<()> {
         if matrix_footer.is_some() {
             // Matrix footer if present
             if matrix_footer.as_ref().unwrap().footer.is_some() {
-                println!("{}", tui_center_text(
-                    matrix_footer.as_ref().unwrap().footer.as_ref().unwrap()));
+                writeln!(out, "{}", tui_center_text(
+                    matrix_footer.as_ref().unwrap().footer.as_ref().unwrap()))?;
             }
 
-            println!("{}", termion::style::Bold);
-            println!("{}", tui_separator_bar("-"));
+            writeln!(out, "{}", self.style(termion::style::Bold))?;
+            writeln!(out, "{}", tui_separator_bar("-"))?;
 
             if matrix_footer.as_ref().unwrap().output_program_footer {
-                println!("{}", tui_lcr_text(
+                writeln!(out, "{}", tui_lcr_text(
                     Some(format!("{}", Utc::now().format("%Y-%m-%dT%H:%M:%SZ"))),
-                    Some("*".repeat(5)),
+                    Some(match &self.profile {
+                        Some(profile) => format!("profile: {}", profile),
+                        None => "*".repeat(5),
+                    }),
                     Some(format!("{}@{}", username(), fallible::hostname().unwrap())),
-                ))
+                ))?;
             }
-            println!("{}", termion::style::Reset);
+            writeln!(out, "{}", self.style(termion::style::Reset))?;
         }
+        Ok(())
     }
 
-    pub(crate) fn output_matrix(&self, matrix: &Matrix) {
-
-        if matrix.header.is_some() {
-            for header in matrix.header.as_ref().unwrap() {
-                println!("{}\n", tui_center_text(header));
+    pub(crate) fn output_matrix(&self, out: &mut dyn Write, matrix: &Matrix) -> io::Result<()> {
+        if let Some(headers) = &matrix.header {
+            for header in headers {
+                writeln!(out, "{}\n", tui_center_text(header))?;
             }
         }
 
@@ -86,26 +121,25 @@ impl TabularOutput {
             .with(Width::wrap(width).priority(PriorityMax))
             .with(Width::increase(width));
 
-        self.output_matrix_data_table(&matrix, &term_size_settings);
+        self.output_matrix_data_table(out, matrix, &term_size_settings)?;
 
         if matrix.aggregate_rows.is_some() {
-            self.output_matrix_aggregate_table(&matrix, &term_size_settings);
+            self.output_matrix_aggregate_table(out, matrix, &term_size_settings)?;
         }
 
         if matrix.notes.is_some() {
-            self.output_notes(&matrix);
+            self.output_notes(out, matrix)?;
         }
+        Ok(())
     }
 
-    fn output_matrix_data_table(&self, matrix: &Matrix, term_size_settings: &Settings<Settings<Settings, Wrap<usize, PriorityMax>>, MinWidth>) {
+    fn output_matrix_data_table(&self, out: &mut dyn Write, matrix: &Matrix, term_size_settings: &Settings<Settings<Settings, Wrap<usize, PriorityMax>>, MinWidth>) -> io::Result<()> {
         // Output rows
 
         let mut builder = Builder::default();
 
-        if matrix.rows.is_some()
-        {
-
-            for row in matrix.rows.as_ref().unwrap() {
+        if let Some(rows) = &matrix.rows {
+            for row in rows {
                 // Push all rows
                 let mut cells: Vec<String> = Vec::new();
                 for cell in row {
@@ -118,45 +152,49 @@ impl TabularOutput {
             }
 
             let mut table = builder.build();
-            let table = table
-                .with(Style::rounded())
-                .with(term_size_settings.clone());
+            self.apply_table_style(&mut table);
+            table.with(term_size_settings.clone());
+
+            // Numbers read better right-aligned.
+            for column in matrix.numeric_columns() {
+                table.modify(Columns::single(column), Alignment::right());
+            }
 
             if matrix.first_rows_header {
                 // table.modify(Rows::first(), Alignment::center());
                 // Experimental - try it out and see if it works
-                table.with(Style::rounded().remove_horizontals()).with(ColumnNames::default());
-            } else {
-                table.with(Style::rounded().remove_horizontals());
+                table.with(ColumnNames::default());
             }
 
-            println!("{table}")
+            writeln!(out, "{table}")?;
         }
+        Ok(())
     }
 
-    fn output_matrix_aggregate_table(&self, matrix: &Matrix, _term_size_settings: &Settings<Settings<Settings, Wrap<usize, PriorityMax>>, MinWidth>) {
+    fn output_matrix_aggregate_table(&self, out: &mut dyn Write, matrix: &Matrix, _term_size_settings: &Settings<Settings<Settings, Wrap<usize, PriorityMax>>, MinWidth>) -> io::Result<()> {
         let mut builder = Builder::default();
 
         for row in matrix.aggregate_rows.as_ref().unwrap() {
-            builder.push_record([&row.name, &row.value.to_string()])
+            builder.push_record([row.name.clone(), row.value.to_string()])
         }
 
         let mut table = builder.build();
 
-        table.with(Style::rounded().remove_horizontals());
+        self.apply_table_style(&mut table);
         table.modify(Columns::first(), Alignment::right());
         // table.modify(Rows::first()));
 
-        println!("{table}")
+        writeln!(out, "{table}")
     }
 
-    fn output_notes(&self, matrix: &Matrix) {
-        println!("\n{}Notes:{}", termion::style::Underline,
-                 termion::style::Reset);
+    fn output_notes(&self, out: &mut dyn Write, matrix: &Matrix) -> io::Result<()> {
+        writeln!(out, "\n{}Notes:{}", self.style(termion::style::Underline),
+                 self.style(termion::style::Reset))?;
 
         for (i, note) in matrix.notes.as_ref().unwrap().iter().enumerate() {
-            println!("{}{}:{} {note}"
-                     , termion::style::Bold, i + 1, termion::style::Reset)
+            writeln!(out, "{}{}:{} {note}"
+                     , self.style(termion::style::Bold), i + 1, self.style(termion::style::Reset))?;
         }
+        Ok(())
     }
 }
@@ -1,5 +1,9 @@
+use std::io::Write;
+
+use crate::errors::jaws_error::JawsError;
 use crate::matrix_handlers::t_matrix_output::MatrixOutput;
 
 pub trait TrMatrixOutputHandler {
-   fn output(&mut self, matrix_output: &MatrixOutput);
+   /// Render the matrix output into `out`, which may be the terminal or a file.
+   fn output(&mut self, matrix_output: &MatrixOutput, out: &mut dyn Write) -> Result<(), JawsError>;
 }
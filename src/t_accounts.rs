@@ -0,0 +1,58 @@
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::errors::jaws_error::{JawsError, JawsErrorKind};
+use crate::Options;
+
+/// An account to run commands in, by assuming a role in it.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Account {
+    pub role_arn: String,
+    /// A name for the account.  If not given, the account's IAM alias is used.
+    #[serde(default)]
+    pub alias: Option<String>,
+}
+
+impl Account {
+    /// The account ID, from the role ARN (`arn:aws:iam::<account>:role/<name>`).
+    pub fn id(&self) -> &str {
+        self.role_arn.split(':').nth(4).unwrap_or_default()
+    }
+}
+
+/// The layout of an `--accounts-file`.
+#[derive(Deserialize)]
+struct AccountsFile {
+    #[serde(default)]
+    accounts: Vec<Account>,
+}
+
+/// All the accounts named with `--role-arn` and in `--accounts-file`, in that order.
+pub fn accounts(options: &Options) -> Result<Vec<Account>, JawsError> {
+    let mut accounts: Vec<Account> = options.role_arns.iter()
+        .map(|arn| Account { role_arn: arn.clone(), alias: None })
+        .collect();
+
+    if let Some(path) = &options.accounts_file {
+        accounts.extend(load_accounts_file(path)?);
+    }
+
+    for account in &accounts {
+        if account.id().len() != 12 || !account.role_arn.starts_with("arn:") {
+            return Err(JawsError::with_kind(JawsErrorKind::Usage,
+                format!("'{}' isn't a role ARN, e.g. arn:aws:iam::123456789012:role/jaws", account.role_arn)));
+        }
+    }
+
+    Ok(accounts)
+}
+
+fn load_accounts_file(path: &Path) -> Result<Vec<Account>, JawsError> {
+    let text = fs::read_to_string(path).map_err(|e| JawsError::with_kind(JawsErrorKind::Config,
+        format!("Couldn't read the accounts file '{}': {}", path.display(), e)).with_source(e))?;
+    let file: AccountsFile = toml::from_str(&text).map_err(|e| JawsError::with_kind(JawsErrorKind::Config,
+        format!("The accounts file '{}' isn't valid: {}", path.display(), e)).with_source(e))?;
+    Ok(file.accounts)
+}
@@ -1,363 +1,282 @@
-// https://awslabs.github.io/aws-sdk-rust/
-
 use std::collections::HashMap;
 
-use aws_config::{BehaviorVersion, Region};
-use aws_sdk_ec2;
-use aws_sdk_ec2::error::ProvideErrorMetadata;
-use aws_sdk_ec2::types::{Filter, Instance, InstanceType, ReservedInstances};
+use futures::stream::{self, StreamExt};
+use aws_sdk_ec2::types::{Instance, InstanceType, ReservedInstances};
 use aws_sdk_iam::types::InstanceProfile;
-use aws_sdk_pricing::types;
-use aws_sdk_pricing::types::FilterType;
-use aws_sdk_sts;
 use aws_sdk_sts::operation::get_caller_identity::GetCallerIdentityOutput;
-use serde_json::Value;
 
-use crate::{handle_and_panic, Options};
-use crate::errors::jaws_error::JawsError;
+use crate::Options;
+use crate::backends::e_backend::Backend;
+use crate::backends::t_fixture_backend::FixtureBackend;
+use crate::backends::t_fixture_set::FixtureSet;
+use crate::backends::t_recording_backend::RecordingBackend;
+use crate::backends::t_sdk_backend::SdkBackend;
+use crate::backends::tr_aws_backend::AwsBackend;
+use crate::e_instance_action::InstanceAction;
+use crate::errors::jaws_error::{JawsError, JawsErrorKind};
+use crate::t_disk_cache::{CacheKind, CachedData, DiskCache};
+use crate::t_ec2_instance::InstanceSpec;
+use crate::t_instance_filters::InstanceFilters;
 use crate::textutils::Textutil;
 
-const TYPE_BATCH_SIZE: i32 = 100;
-
 /// This type contains low-level functionality for handling AWS calls.  Several datasets
 /// are cached, therefore clients should prefer to reuse this type, rather than reinstantiating
 /// it.
+///
+/// The calls themselves are made by a backend chosen with `--backend`: the AWS SDK, or canned
+/// data for offline use.  With `--record` the backend's responses are also saved, and with
+/// `--replay` they're served from a recording.
 pub struct AWSHandler {
-    instance_profile_cache: HashMap<String, InstanceProfile>,
+    backend: Box<dyn AwsBackend>,
+    instance_profile_cache: BulkCache<HashMap<String, InstanceProfile>>,
     instance_profile_ssm_mapping_cache: HashMap<String, bool>,
-    specmap: HashMap<String, String>,
-    odm_rate_cache: HashMap<InstanceType, f32>,
-    region: Option<String>,
+    specmap: BulkCache<HashMap<String, InstanceSpec>>,
+    odm_rate_cache: Option<CachedData<HashMap<String, f32>>>,
+    /// Whether rates have been added since the cache was loaded, so that it needs storing.
+    odm_rates_added: bool,
+    disk_cache: DiskCache,
+    region: String,
     textutil: Textutil,
 }
 
 
 impl AWSHandler {
     /// Get a new handler, primed with any optional elements.
-    /// If it is not possible to construct a valid config, this function aborts as a diverging function.
-    pub async fn new(options: &Options) -> Self {
-        let mut handler = AWSHandler {
-            instance_profile_cache: HashMap::new(),
-            instance_profile_ssm_mapping_cache: HashMap::new(),
-            specmap: HashMap::new(),
-            odm_rate_cache: HashMap::new(),
-            region: None,
-            textutil: Textutil::new(options),
+    /// Fails with a configuration error if no region can be determined.
+    pub async fn new(options: &Options) -> Result<Self, JawsError> {
+        // Canned data is never cached: it's already on disk, and isn't real.  Neither is data
+        // from a custom endpoint, such as LocalStack.
+        let (backend, region, disk_cache): (Box<dyn AwsBackend>, String, DiskCache) = match (&options.replay, &options.backend) {
+            (Some(file), _) => {
+                let (backend, region) = FixtureBackend::from_fixtures(FixtureSet::load_file(file)?, options)?;
+                (Box::new(backend), region, DiskCache::disabled())
+            }
+            (None, Backend::Sdk) => {
+                let (backend, region) = SdkBackend::new(options).await?;
+                let disk_cache = if options.has_custom_endpoints() { DiskCache::disabled() } else { DiskCache::new(options) };
+                (Box::new(backend), region, disk_cache)
+            }
+            (None, Backend::Fixtures(dir)) => {
+                let (backend, region) = FixtureBackend::from_dir(dir, options)?;
+                (Box::new(backend), region, DiskCache::disabled())
+            }
         };
-        // Load the region from options, if Some.  If None, load using AWS defaulting.
-        handler.region = match &options.region {
-            None => {
-                let sdk_config = aws_config::load_defaults(BehaviorVersion::latest()).await;
-
-                match sdk_config.region() {
-                    None => { handle_and_panic(JawsError::new("No AWS region could be calculated from your configuration.\n\nEnsure AWS_REGION and/or AWS_PROFILE are set.".to_string())) },
-                    Some(region) => {
-                        Some(region.to_string())
-                    }
-                }
+
+        let backend: Box<dyn AwsBackend> = match &options.record {
+            Some(file) => {
+                let account = options.assume_role.as_ref().and_then(|arn| arn.split(':').nth(4)).map(|a| a.to_string());
+                Box::new(RecordingBackend::start(backend, &region, account, file.clone()).await?)
             }
-            Some(region) => Some(region.to_string()),
+            None => backend,
         };
 
-        // Set this in the environment if it's set.  The AWS library will then pick it up
-        // during the various client creation statements.
-        if handler.region.is_some() {
-            std::env::set_var("AWS_DEFAULT_REGION", handler.region.as_ref().unwrap());
-        }
+        Ok(Self::with_backend(backend, region, disk_cache, options))
+    }
 
-        handler
+    /// Get a handler serving `fixtures`, with nothing cached on disk.
+    #[cfg(test)]
+    pub fn from_fixtures(fixtures: FixtureSet, options: &Options) -> Result<Self, JawsError> {
+        let (backend, region) = FixtureBackend::from_fixtures(fixtures, options)?;
+        Ok(Self::with_backend(Box::new(backend), region, DiskCache::disabled(), options))
     }
-    /// Return the current context's STS caller identity.
-    pub async fn sts_get_caller_identity(&self) -> Result<GetCallerIdentityOutput, JawsError> {
-        let res =
-            aws_sdk_sts::Client::new(&aws_config::load_defaults(BehaviorVersion::latest()).await)
-                .get_caller_identity()
-                .send()
-                .await;
-
-        match res {
-            Ok(output) => Ok(output),
-            Err(e) => {
-                // let service_error = e.into_service_error();
-                // let s = service_error.message();
-                //
-                // let message = match s {
-                //     None => { "No message returned from SDK" }
-                //     Some(_) => { s.unwrap() }
-                // };
-
-                Err(JawsError::new(format!("Ensure your AWS credentials are set correctly in the environment.\n\nThe underlying error is:\n\t{:?}", e.into_service_error().message().unwrap_or("No message returned from SDK."))))
-            }
+
+    fn with_backend(backend: Box<dyn AwsBackend>, region: String, disk_cache: DiskCache, options: &Options) -> Self {
+        AWSHandler {
+            backend,
+            instance_profile_cache: BulkCache::Empty,
+            instance_profile_ssm_mapping_cache: HashMap::new(),
+            specmap: BulkCache::Empty,
+            odm_rate_cache: None,
+            odm_rates_added: false,
+            disk_cache,
+            region,
+            textutil: Textutil::new(options),
         }
     }
 
-    pub async fn ec2_get_all(&self) -> Result<Vec<Instance>, JawsError> {
-        let mut instances: Vec<Instance> = Vec::new();
+    /// Return the current context's STS caller identity.
+    pub async fn sts_get_caller_identity(&self) -> Result<GetCallerIdentityOutput, JawsError> {
+        self.backend.get_caller_identity().await
+    }
 
-        let client: aws_sdk_ec2::Client =
-            aws_sdk_ec2::Client::new(&aws_config::load_defaults(BehaviorVersion::latest()).await);
+    /// The instances in the region which match `filters`.  The filtering is done by AWS.
+    pub async fn ec2_get_all(&self, filters: &InstanceFilters) -> Result<Vec<Instance>, JawsError> {
+        self.backend.describe_instances(&filters.to_sdk()).await
+    }
 
-        let resp_result = client.describe_instances().send().await;
+    /// Start, stop, reboot, terminate or hibernate instances.  With `dry_run`, only check that
+    /// AWS would allow it.
+    pub async fn ec2_change_instance_state(&self, action: InstanceAction, instance_ids: &[String], dry_run: bool)
+                                           -> Result<(), JawsError> {
+        self.backend.change_instance_state(action, instance_ids, dry_run).await
+    }
 
-        match resp_result {
-            Ok(resp) => {
-                for reservation in resp.reservations() {
-                    let list: &[Instance] = reservation.instances();
-                    for instance in list {
-                        // println!("{:?}", instance);
-                        instances.push(instance.to_owned());
-                    }
-                }
+    /// The account's IAM alias, if it has one.
+    pub async fn iam_get_account_alias(&self) -> Result<Option<String>, JawsError> {
+        self.backend.get_account_alias().await
+    }
 
-                Ok(instances)
-            }
-            Err(error) => Err(JawsError::new(format!("{}", error))),
-        }
+    /// The names of the regions enabled for the account, in order.
+    pub async fn ec2_get_regions(&self) -> Result<Vec<String>, JawsError> {
+        self.textutil.notify_comms(Some("getting regions".to_string()));
+        self.backend.describe_regions().await
     }
 
-    pub async fn instance_can_ssm(&mut self, instance: &Instance) -> bool {
+    /// Work out which instance profiles allow SSM access, i.e. whose role has the
+    /// `AmazonSSMManagedInstanceCore` policy attached.  Profiles which haven't been checked
+    /// before are checked concurrently, at most `concurrency` at a time.  Each profile gets its
+    /// own result, so that one failure doesn't affect the others.
+    pub async fn instance_profiles_can_ssm(&mut self, profile_arns: &[String], concurrency: usize)
+                                           -> HashMap<String, Result<bool, JawsError>> {
+        let mut results: HashMap<String, Result<bool, JawsError>> = HashMap::new();
+
         // Load cache if necessary
-        let client: aws_sdk_iam::Client =
-            aws_sdk_iam::Client::new(&aws_config::load_defaults(BehaviorVersion::latest()).await);
-
-        if self.instance_profile_cache.len() == 0 {
-            self.textutil.notify_comms(Some("filling Instance Profile cache".to_string()));
-            for ip in client
-                .list_instance_profiles()
-                .send()
-                .await
-                .unwrap()
-                .instance_profiles()
-            {
-                self.instance_profile_cache
-                    .insert(ip.arn().to_string(), ip.clone());
-            }
+        if let BulkCache::Empty = self.instance_profile_cache {
+            self.instance_profile_cache = BulkCache::from_result(self.load_instance_profiles().await);
         }
 
-        // Instance may not have a profile
-        if instance.iam_instance_profile().is_none() {
-            return false;
+        // Answer from the cache where possible.
+        let mut unchecked: Vec<String> = Vec::new();
+        for arn in profile_arns {
+            match self.instance_profile_ssm_mapping_cache.get(arn) {
+                Some(answer) => { results.insert(arn.clone(), Ok(*answer)); }
+                None => unchecked.push(arn.clone()),
+            }
         }
 
-        // Check whether the answer is cached already
-        let ip_arn = instance.iam_instance_profile().unwrap().arn().unwrap();
-        let answer: Option<&bool> = self.instance_profile_ssm_mapping_cache.get(ip_arn);
-
-        // ... yes.
-        if answer.is_some() {
-            return *answer.unwrap();
-        }
+        self.textutil.notify_comms(Some(format!("getting IAM role information [{} profiles]", unchecked.len())));
 
-        // no...
+        let this: &AWSHandler = self;
+        let checked: Vec<(String, Result<bool, JawsError>)> = stream::iter(unchecked)
+            .map(|arn| async move {
+                let result = this.profile_can_ssm(&arn).await;
+                (arn, result)
+            })
+            .buffer_unordered(concurrency.max(1))
+            .collect()
+            .await;
 
-        // Check whether the instance has policy AmazonSSMManagedInstanceCore in its role
-        self.textutil.notify_comms(Some(format!(
-            "getting IAM role information {:?}",
-            instance.iam_instance_profile().unwrap().id().unwrap()
-        )));
+        for (arn, result) in checked {
+            if let Ok(answer) = &result {
+                self.instance_profile_ssm_mapping_cache.insert(arn.clone(), *answer);
+            }
+            results.insert(arn, result);
+        }
 
-        let instance_profile_arn = instance.iam_instance_profile().unwrap().arn().unwrap();
-        let ip = self
-            .instance_profile_cache
-            .get(instance_profile_arn)
-            .unwrap();
+        results
+    }
 
-        // Get the role in this instance profile -- there can be only one
-        let role = &ip.roles()[0];
+    /// Check whether an instance profile's role has the SSM policy attached.
+    async fn profile_can_ssm(&self, ip_arn: &str) -> Result<bool, JawsError> {
+        let ip = self.instance_profile_cache.get()?
+            .get(ip_arn)
+            .ok_or_else(|| JawsError::with_kind(JawsErrorKind::NotFound,
+                format!("Instance profile {} wasn't found in this account", ip_arn)))?;
+
+        // Get the role in this instance profile -- there can be only one, but there may be none.
+        let role = match ip.roles().first() {
+            Some(role) => role,
+            None => return Ok(false),
+        };
 
         // Load the policies and check whether the SSM policy is in there.
-        let policies = client
-            .list_attached_role_policies()
-            .role_name(role.role_name())
-            .send()
-            .await
-            .unwrap();
-
-        for policy in policies.attached_policies() {
-            if policy.policy_name().unwrap() == "AmazonSSMManagedInstanceCore" {
-                self.instance_profile_ssm_mapping_cache
-                    .insert(ip_arn.to_string(), true);
-                return true;
-            }
-        }
-
-        self.instance_profile_ssm_mapping_cache
-            .insert(ip_arn.to_string(), false);
-        false
+        let policies = self.backend.list_attached_role_policies(role.role_name()).await?;
+        Ok(policies.iter().any(|policy| policy == "AmazonSSMManagedInstanceCore"))
     }
 
-    pub async fn get_instance_spec(&mut self, instance_type_key: &str) -> Option<String> {
-        if self.specmap.len() == 0 {
-            self.populate_spec_map().await;
+    /// Look up the hardware specification of an instance type.  All types are loaded on the
+    /// first call.  Returns `None` for a type AWS doesn't describe.
+    pub async fn get_instance_spec(&mut self, instance_type_key: &str) -> Result<Option<InstanceSpec>, JawsError> {
+        if let BulkCache::Empty = self.specmap {
+            self.specmap = BulkCache::from_result(self.load_spec_map().await);
         }
 
-        let value = self.specmap.get(instance_type_key);
-
-        // This is rather ugly but avoids returning an Option<&String>.
-        return match value {
-            Some(k) => Some(k.to_string()),
-            None => None,
-        };
+        Ok(self.specmap.get()?.get(instance_type_key).copied())
     }
 
+    /// Get all active reservations.
     pub async fn reservations_get_live(&self) -> Result<Vec<ReservedInstances>, JawsError> {
-        let client: aws_sdk_ec2::Client =
-            aws_sdk_ec2::Client::new(&aws_config::load_defaults(BehaviorVersion::latest()).await);
-
-        let result = client
-            .describe_reserved_instances()
-            .filters(Filter::builder().name("state").values("active").build())
-            .send()
-            .await;
+        self.textutil.notify_comms(Some("getting reservations".to_string()));
 
-        match result {
-            Ok(resp) => Ok(resp.reserved_instances.unwrap()),
-            Err(error) => Err(JawsError::new(format!("{}", error))),
-        }
+        self.backend.describe_active_reserved_instances().await
     }
 
-    pub(crate) async fn get_odm_rate(&mut self, instance_type: &InstanceType) -> f32 {
-        // Get the on-demand rate for a given instance type.
-
-        // Check if it's already in the cache
-        if !self.odm_rate_cache.contains_key(instance_type) {
-
-            // Get the on-demand rate and cache it, then return it
-            // AWS Pricing is not available everywhere - we use eu-central-1 to access it.
-
-            let mut config = aws_config::load_defaults(BehaviorVersion::latest()).await;
-            config = config.to_builder().region(Region::from_static("eu-central-1")).build();
-
-            let client = aws_sdk_pricing::Client::new(&config);
-
-            let instance_type_filter = types::Filter::builder()
-                .r#type(FilterType::TermMatch)
-                .field("instanceType")
-                .value(instance_type.as_str())
-                .build().unwrap();
-            let region_code_filter = types::Filter::builder()
-                .r#type(FilterType::TermMatch)
-                .field("regionCode")
-                .value(self.region.as_ref().unwrap())
-                .build().unwrap();
-            let software_filter = types::Filter::builder()
-                .r#type(FilterType::TermMatch)
-                .field("preInstalledSw")
-                .value("NA")
-                .build().unwrap();
-            let tenancy_filter = types::Filter::builder()
-                .r#type(FilterType::TermMatch)
-                .field("tenancy")
-                .value("Shared")
-                .build().unwrap();
-            let product_family_filter = types::Filter::builder()
-                .r#type(FilterType::TermMatch)
-                .field("productfamily")
-                .value("compute instance")
-                .build().unwrap();
-            let os_filter = types::Filter::builder()
-                .r#type(FilterType::TermMatch)
-                .field("operatingSystem")
-                .value("Linux")
-                .build().unwrap();
-            let capacity_filter = types::Filter::builder()
-                .r#type(FilterType::TermMatch)
-                .field("capacityStatus")
-                .value("Used")
-                .build().unwrap();
-
-            let result = client
-                .get_products()
-                .filters(instance_type_filter)
-                .filters(region_code_filter)
-                .filters(software_filter)
-                .filters(product_family_filter)
-                .filters(tenancy_filter)
-                .filters(os_filter)
-                .filters(capacity_filter)
-                .service_code("AmazonEC2")
-                .send().await.unwrap();
-
-            // for price in result.price_list() {
-            //     println!("{}", price);
-            // }
-
-            assert_eq!(result.price_list().len(), 1, "ODM pricing data search for '{}' returned non-unique result ({}).", instance_type.to_string(), result.price_list().len());
-
-            let price_data: Value = serde_json::from_str(result.price_list()[0].as_str()).unwrap();
-
-            // The next stage is a bit fiddly, because the name of the key after the tree node "OnDemand"
-            // is dynamic.`as_object()` converts it to a map, so we can get the first value.
-            let mut odm: &Value = &price_data["terms"]["OnDemand"].as_object().unwrap()
-                .values().next().unwrap();
-
-            // ... And again.
-            odm = odm["priceDimensions"].as_object().unwrap()
-                .values().next().unwrap();
-
-            odm = &odm["pricePerUnit"]["USD"];
-
-            // Get the final price out of the string.
-            let price: f32 = odm.as_str().unwrap().parse::<f32>().unwrap();
-
-            self.odm_rate_cache.insert(instance_type.clone(), price);
+    /// Get the Linux on-demand hourly rate for an instance type in the current region, in USD.
+    pub(crate) async fn get_odm_rate(&mut self, instance_type: &InstanceType) -> Result<f32, JawsError> {
+        // Check if it's already in the cache, loading the cache from disk on first use.
+        let odm_rates = match &mut self.odm_rate_cache {
+            Some(odm_rates) => odm_rates,
+            cache => cache.insert(self.disk_cache.load(&self.region, CacheKind::OdmRates)
+                .unwrap_or_else(|| CachedData::now(HashMap::new()))),
+        };
+        if let Some(rate) = odm_rates.data.get(instance_type.as_str()) {
+            return Ok(*rate);
         }
 
-        return *self.odm_rate_cache.get(instance_type).unwrap();
+        // Get the on-demand rate and cache it, then return it
+        let price = self.backend.get_odm_rate(&self.region, instance_type.as_str()).await?;
+
+        // The rates are stored when the handler is done with, rather than after every one.
+        if let Some(odm_rates) = &mut self.odm_rate_cache {
+            odm_rates.data.insert(instance_type.as_str().to_string(), price);
+            self.odm_rates_added = true;
+        }
+        Ok(price)
     }
 
     // -------------------------------------------------------------------------------
     // Private
     // -------------------------------------------------------------------------------
 
-    async fn populate_spec_map(&mut self) {
-        let client: aws_sdk_ec2::Client =
-            aws_sdk_ec2::Client::new(&aws_config::load_defaults(BehaviorVersion::latest()).await);
-
-        let mut response = client
-            .describe_instance_types()
-            .set_max_results(Some(TYPE_BATCH_SIZE))
-            .send()
-            .await
-            .unwrap();
-
-        let mut loaded: usize = 0;
-
-        loop {
-            self.textutil.notify_comms(Some(
-                format!("getting instance types [{}]", loaded).to_string(),
-            ));
-
-            for t in response.instance_types.as_ref().unwrap() {
-                let key = t.instance_type().unwrap().as_str().to_string();
-                self.specmap.insert(
-                    key,
-                    format!(
-                        "{}/{}",
-                        t.v_cpu_info().unwrap().default_v_cpus().unwrap(),
-                        t.memory_info().unwrap().size_in_mib().unwrap() / 1024
-                    ),
-                );
-            }
+    async fn load_instance_profiles(&self) -> Result<HashMap<String, InstanceProfile>, JawsError> {
+        Ok(self.backend.list_instance_profiles().await?
+            .into_iter()
+            .map(|ip| (ip.arn().to_string(), ip))
+            .collect())
+    }
 
-            loaded = self.specmap.len();
-
-            response = match response.next_token() {
-                Some(token) => {
-                    client
-                        .describe_instance_types()
-                        .set_next_token(Some(token.to_string()))
-                        .set_max_results(Some(TYPE_BATCH_SIZE))
-                        .send()
-                        .await
-                        .unwrap()
-                    // And loop again
-                }
-                None => {
-                    break;
-                    // And stop looping
-                }
-            }
+    async fn load_spec_map(&self) -> Result<HashMap<String, InstanceSpec>, JawsError> {
+        if let Some(cached) = self.disk_cache.load(&self.region, CacheKind::InstanceSpecs) {
+            return Ok(cached.data);
+        }
+
+        let specmap = self.backend.describe_instance_types().await?;
+        self.disk_cache.store(&self.region, CacheKind::InstanceSpecs, &CachedData::now(&specmap));
+        Ok(specmap)
+    }
+}
+
+/// Store any newly fetched on-demand rates.  The file keeps the time of its first entry, so
+/// that every rate in it expires in time.
+impl Drop for AWSHandler {
+    fn drop(&mut self) {
+        if let (true, Some(odm_rates)) = (self.odm_rates_added, &self.odm_rate_cache) {
+            self.disk_cache.store(&self.region, CacheKind::OdmRates, odm_rates);
+        }
+    }
+}
+
+/// A dataset which is loaded in bulk on first use.  If loading fails, the failure is
+/// remembered so that later lookups report it without calling AWS again.
+enum BulkCache<T> {
+    Empty,
+    Loaded(T),
+    Failed(JawsErrorKind, String),
+}
+
+impl<T> BulkCache<T> {
+    fn from_result(result: Result<T, JawsError>) -> Self {
+        match result {
+            Ok(data) => BulkCache::Loaded(data),
+            Err(e) => BulkCache::Failed(e.kind(), e.to_string()),
+        }
+    }
+
+    fn get(&self) -> Result<&T, JawsError> {
+        match self {
+            BulkCache::Loaded(data) => Ok(data),
+            BulkCache::Failed(kind, message) => Err(JawsError::with_kind(*kind, message.clone())),
+            BulkCache::Empty => Err(JawsError::new("Cache used before it was loaded".to_string())),
         }
     }
 }
@@ -0,0 +1,319 @@
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::PathBuf;
+
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches};
+use serde::Deserialize;
+
+use crate::backends::t_service_endpoint::ServiceEndpoint;
+use crate::e_output_format::OutputFormat;
+use crate::e_table_style::TableStyle;
+use crate::errors::jaws_error::{JawsError, JawsErrorKind};
+use crate::Options;
+
+/// The user's configuration file (`~/.config/jaws/config.toml` on Linux, or `--config`).
+/// Everything in it is a default: options given on the command line always win.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub region: Option<String>,
+    pub profile: Option<String>,
+    pub output_format: Option<String>,
+    pub wide: bool,
+    pub table_style: Option<String>,
+    pub endpoint_url: Option<String>,
+    /// Endpoint URLs for single services, by service name, like `--service-endpoint`.
+    pub endpoints: BTreeMap<String, String>,
+    /// The columns to show for each command's report, by command name, like `--columns`.
+    pub columns: BTreeMap<String, Vec<String>>,
+    /// Named invocations, run as `jaws @name`.
+    pub preset: BTreeMap<String, Preset>,
+}
+
+/// A named invocation: a command, and the options to run it with, named as on the command
+/// line without the dashes, e.g. `where = "Name ~ web-*"` or `show_unused = true`.
+#[derive(Debug, Deserialize)]
+pub struct Preset {
+    pub command: String,
+    #[serde(flatten)]
+    pub options: BTreeMap<String, toml::Value>,
+}
+
+impl Config {
+    /// The default location of the configuration file, if the platform has one.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("jaws").join("config.toml"))
+    }
+
+    /// Load the configuration from `path`, or from the default location.  A missing default
+    /// file is an empty configuration; a missing `--config` file is an error.
+    pub fn load(path: Option<PathBuf>) -> Result<Self, JawsError> {
+        let (path, required) = match path {
+            Some(path) => (path, true),
+            None => match Self::default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Config::default()),
+            },
+        };
+
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if !required && e.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => return Err(JawsError::with_kind(JawsErrorKind::Config,
+                format!("Couldn't read the configuration file '{}': {}", path.display(), e)).with_source(e)),
+        };
+
+        toml::from_str(&text).map_err(|e| JawsError::with_kind(JawsErrorKind::Config,
+            format!("The configuration file '{}' isn't valid.", path.display())).with_source(e))
+    }
+
+    /// Replace an `@name` argument in place of the subcommand with the preset's command and
+    /// options.  Arguments after it follow the preset's, so they can add to or override them.
+    /// Option values starting with `@` are left alone.
+    pub fn expand_preset(&self, args: Vec<OsString>) -> Result<Vec<OsString>, JawsError> {
+        let Some(position) = command_position(&args)
+            .filter(|&position| args[position].to_str().is_some_and(|a| a.starts_with('@'))) else {
+            return Ok(args);
+        };
+
+        let name = &args[position].to_str().unwrap()[1..];
+        let preset = self.preset.get(name).ok_or_else(|| {
+            let known = self.preset.keys().map(|k| k.as_str()).collect::<Vec<_>>().join(", ");
+            JawsError::with_kind(JawsErrorKind::Usage, if known.is_empty() {
+                format!("There's no preset '{}'; none are configured", name)
+            } else {
+                format!("There's no preset '{}'; use one of: {}", name, known)
+            })
+        })?;
+
+        let mut expanded: Vec<OsString> = args[..position].to_vec();
+        expanded.extend(preset.command.split_whitespace().map(OsString::from));
+        for (option, value) in &preset.options {
+            expanded.extend(preset_args(name, option, value)?);
+        }
+        expanded.extend(args[position + 1..].iter().cloned());
+        Ok(expanded)
+    }
+
+    /// Fill in every option not given on the command line (or by a preset) from the
+    /// configuration.
+    pub fn apply(&self, options: &mut Options, matches: &ArgMatches) -> Result<(), JawsError> {
+        // A profile given on the command line brings its own region.
+        if options.region.is_none() && options.regions.is_empty() && !options.all_regions && options.profile.is_none() {
+            options.region = self.region.clone();
+        }
+        if options.profile.is_none() {
+            options.profile = self.profile.clone();
+        }
+        if !options.no_wide {
+            options.wide |= self.wide;
+        }
+
+        if let Some(format) = &self.output_format {
+            if matches.value_source("output_format") != Some(ValueSource::CommandLine) {
+                options.output_format = Some(format.parse::<OutputFormat>().map_err(invalid)?);
+            }
+        }
+        if let (None, Some(style)) = (options.table_style, &self.table_style) {
+            options.table_style = Some(style.parse::<TableStyle>().map_err(invalid)?);
+        }
+
+        if options.endpoint_url.is_none() {
+            options.endpoint_url = self.endpoint_url.clone();
+        }
+        // The command line's endpoints come last, so that they take precedence.
+        let mut service_endpoints: Vec<ServiceEndpoint> = Vec::new();
+        for (service, url) in &self.endpoints {
+            service_endpoints.push(format!("{}={}", service, url).parse().map_err(invalid)?);
+        }
+        service_endpoints.append(&mut options.service_endpoint);
+        options.service_endpoint = service_endpoints;
+
+        if options.columns.is_empty() {
+            // Not for a command's own subcommands (e.g. `ec2 stop`), which report differently.
+            let command = matches.subcommand()
+                .filter(|(_, command)| command.subcommand_name().is_none())
+                .map(|(name, _)| name);
+            if let Some(columns) = command.and_then(|command| self.columns.get(command)) {
+                options.columns = columns.clone();
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Parse the command line, after expanding any preset, and complete it from the
+/// configuration file.  Like `Options::parse`, exits if the command line is invalid.
+pub fn parse_options() -> Result<Options, JawsError> {
+    let args: Vec<OsString> = std::env::args_os().collect();
+
+    let config = Config::load(config_path(&args))?;
+    let args = config.expand_preset(args)?;
+
+    let matches = Options::command().get_matches_from(args);
+    let mut options = Options::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    config.apply(&mut options, &matches)?;
+    Ok(options)
+}
+
+/// The position of the subcommand: the first argument after the program name which is
+/// neither a global option nor the value of one.
+fn command_position(args: &[OsString]) -> Option<usize> {
+    let command = Options::command();
+    let takes_value = |arg: Option<&clap::Arg>| arg.is_some_and(|arg| arg.get_action().takes_values());
+
+    let mut position = 1;
+    while position < args.len() {
+        let arg = args[position].to_string_lossy();
+        if arg == "--" {
+            return Some(position + 1).filter(|&position| position < args.len());
+        }
+
+        if let Some(long) = arg.strip_prefix("--") {
+            if !long.contains('=') && takes_value(command.get_arguments().find(|a| a.get_long() == Some(long))) {
+                position += 1;
+            }
+        } else if let Some(shorts) = arg.strip_prefix('-').filter(|shorts| !shorts.is_empty()) {
+            // Short options may be grouped, e.g. `-wr eu-west-1`.  One which takes a value
+            // takes the rest of the group, or else the next argument.
+            for (n, short) in shorts.char_indices() {
+                if takes_value(command.get_arguments().find(|a| a.get_short() == Some(short))) {
+                    if n + short.len_utf8() == shorts.len() {
+                        position += 1;
+                    }
+                    break;
+                }
+            }
+        } else {
+            return Some(position);
+        }
+        position += 1;
+    }
+    None
+}
+
+/// The `--config` file named on the command line, which has to be known before it's parsed.
+fn config_path(args: &[OsString]) -> Option<PathBuf> {
+    let mut args = args.iter().map(|arg| arg.to_string_lossy());
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            return args.next().map(|path| PathBuf::from(path.as_ref()));
+        }
+        if let Some(path) = arg.strip_prefix("--config=") {
+            return Some(PathBuf::from(path));
+        }
+    }
+    None
+}
+
+/// The command line arguments for one preset option: `--option=value`, repeated for each
+/// value of an array, or `--option` alone for `true`.
+fn preset_args(preset: &str, option: &str, value: &toml::Value) -> Result<Vec<OsString>, JawsError> {
+    let flag = format!("--{}", option.replace('_', "-"));
+
+    Ok(match value {
+        toml::Value::Boolean(true) => vec![flag.into()],
+        toml::Value::Boolean(false) => Vec::new(),
+        toml::Value::String(s) => vec![format!("{}={}", flag, s).into()],
+        toml::Value::Integer(_) | toml::Value::Float(_) => vec![format!("{}={}", flag, value).into()],
+        toml::Value::Array(values) => {
+            let mut args = Vec::new();
+            for value in values {
+                args.extend(preset_args(preset, option, value)?);
+            }
+            args
+        }
+        _ => return Err(JawsError::with_kind(JawsErrorKind::Config,
+            format!("Preset '{}' option '{}' must be a string, number, boolean or array", preset, option))),
+    })
+}
+
+fn invalid(message: String) -> JawsError {
+    JawsError::with_kind(JawsErrorKind::Config, format!("The configuration file isn't valid: {}", message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(toml: &str) -> Config {
+        toml::from_str(toml).unwrap()
+    }
+
+    fn args(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn expands_a_preset_in_place() {
+        let config = config(r#"
+            [preset.expiring]
+            command = "res"
+            show_unused = true
+            all_regions = false
+            sort_by = ["Days", "Type"]
+            where = "Days < 60"
+        "#);
+
+        let expanded = config.expand_preset(args(&["jaws", "-o", "json", "@expiring", "--wide"])).unwrap();
+        assert_eq!(expanded, args(&["jaws", "-o", "json", "res", "--show-unused", "--sort-by=Days", "--sort-by=Type",
+                                    "--where=Days < 60", "--wide"]));
+    }
+
+    #[test]
+    fn expands_a_preset_after_global_options() {
+        let config = config(r#"
+            [preset.web]
+            command = "ec2"
+            name = "web-*"
+        "#);
+
+        let expanded = config.expand_preset(args(&["jaws", "--config", "@cfg.toml", "-wr", "@eu", "@web"])).unwrap();
+        assert_eq!(expanded, args(&["jaws", "--config", "@cfg.toml", "-wr", "@eu", "ec2", "--name=web-*"]));
+    }
+
+    #[test]
+    fn leaves_option_values_starting_with_at_alone() {
+        let config = config(r#"
+            [preset.web]
+            command = "ec2"
+        "#);
+
+        for argv in [
+            &["jaws", "ec2", "--name", "@ops"][..],
+            &["jaws", "--output-file", "@out.json", "ec2"],
+            &["jaws", "--output-file=@out.json", "-o", "@json", "ec2"],
+            &["jaws", "--config", "@cfg.toml", "res", "--where", "Name == @web"],
+        ] {
+            assert_eq!(config.expand_preset(args(argv)).unwrap(), args(argv));
+        }
+    }
+
+    #[test]
+    fn leaves_arguments_without_a_preset_alone() {
+        let expanded = Config::default().expand_preset(args(&["jaws", "ec2", "--name", "web-*"])).unwrap();
+        assert_eq!(expanded, args(&["jaws", "ec2", "--name", "web-*"]));
+    }
+
+    #[test]
+    fn rejects_unknown_presets_and_options() {
+        let config = config(r#"
+            [preset.web]
+            command = "ec2"
+            [preset.odd]
+            command = "ec2"
+            tag = { env = "prod" }
+        "#);
+
+        let error = config.expand_preset(args(&["jaws", "@db"])).unwrap_err();
+        assert_eq!(error.kind(), JawsErrorKind::Usage);
+        assert_eq!(error.to_string(), "There's no preset 'db'; use one of: odd, web");
+
+        let error = config.expand_preset(args(&["jaws", "@odd"])).unwrap_err();
+        assert_eq!(error.kind(), JawsErrorKind::Config);
+        assert!(error.to_string().contains("option 'tag' must be"));
+    }
+}
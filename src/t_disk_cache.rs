@@ -0,0 +1,194 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::errors::jaws_error::{JawsError, JawsErrorKind};
+use crate::Options;
+
+/// The kinds of reference data kept in the on-disk cache.  Each is cached per region.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CacheKind {
+    /// Hardware specifications of all instance types.
+    InstanceSpecs,
+    /// On-demand list prices, by instance type.
+    OdmRates,
+}
+
+impl CacheKind {
+    pub const ALL: [CacheKind; 2] = [CacheKind::InstanceSpecs, CacheKind::OdmRates];
+
+    /// How long cached data of this kind is used before it's fetched again.
+    pub fn ttl(&self) -> TimeDelta {
+        match self {
+            CacheKind::InstanceSpecs => TimeDelta::days(7),
+            CacheKind::OdmRates => TimeDelta::days(1),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CacheKind::InstanceSpecs => "instance-specs",
+            CacheKind::OdmRates => "odm-rates",
+        }
+    }
+}
+
+/// How the cache is used for this run.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CacheMode {
+    /// Use fresh cached data, and cache anything fetched.
+    Normal,
+    /// Ignore cached data, but cache anything fetched (`--refresh-cache`).
+    Refresh,
+    /// Neither read nor write the cache (`--no-cache`).
+    Disabled,
+}
+
+/// Cached data, as stored on disk: the data itself and when it was fetched from AWS.
+#[derive(Serialize, Deserialize)]
+pub struct CachedData<T> {
+    pub fetched_at: DateTime<Utc>,
+    pub data: T,
+}
+
+impl<T> CachedData<T> {
+    pub fn now(data: T) -> Self {
+        Self { fetched_at: Utc::now(), data }
+    }
+}
+
+/// A persistent cache of slow-changing AWS reference data, stored as JSON files under the
+/// user's cache directory (e.g. `~/.cache/jaws/<region>/<kind>.json` on Linux).  The cache is
+/// best-effort: data which can't be read is treated as missing, and failures to write are
+/// ignored, so that a broken cache never stops a command from working.
+pub struct DiskCache {
+    root: Option<PathBuf>,
+    mode: CacheMode,
+}
+
+impl DiskCache {
+    pub fn new(options: &Options) -> Self {
+        let mode = if options.no_cache {
+            CacheMode::Disabled
+        } else if options.refresh_cache || options.record.is_some() {
+            CacheMode::Refresh
+        } else {
+            CacheMode::Normal
+        };
+
+        Self { root: Self::root(), mode }
+    }
+
+    /// A cache which is never read or written.
+    pub fn disabled() -> Self {
+        Self { root: None, mode: CacheMode::Disabled }
+    }
+
+    /// The cache directory, if the platform has one.
+    pub fn root() -> Option<PathBuf> {
+        dirs::cache_dir().map(|dir| dir.join("jaws"))
+    }
+
+    /// Load cached data, if it exists and is younger than the kind's TTL.
+    pub fn load<T: DeserializeOwned>(&self, region: &str, kind: CacheKind) -> Option<CachedData<T>> {
+        if self.mode != CacheMode::Normal {
+            return None;
+        }
+
+        let cached: CachedData<T> = read_file(&self.path(region, kind)?)?;
+        if Utc::now() - cached.fetched_at < kind.ttl() {
+            Some(cached)
+        } else {
+            None
+        }
+    }
+
+    pub fn store<T: Serialize>(&self, region: &str, kind: CacheKind, cached: &CachedData<T>) {
+        if self.mode == CacheMode::Disabled {
+            return;
+        }
+
+        if let (Some(path), Ok(json)) = (self.path(region, kind), serde_json::to_string(cached)) {
+            if let Some(dir) = path.parent() {
+                _ = fs::create_dir_all(dir);
+            }
+            _ = fs::write(path, json);
+        }
+    }
+
+    fn path(&self, region: &str, kind: CacheKind) -> Option<PathBuf> {
+        self.root.as_ref().map(|root| root.join(region).join(format!("{}.json", kind.name())))
+    }
+}
+
+/// A summary of one cache file, for `jaws cache status`.
+pub struct CacheEntryStatus {
+    pub region: String,
+    pub kind: CacheKind,
+    /// The number of items in the file, or None if it couldn't be read.
+    pub entries: Option<usize>,
+    pub fetched_at: Option<DateTime<Utc>>,
+}
+
+impl CacheEntryStatus {
+    pub fn is_fresh(&self) -> bool {
+        self.fetched_at.is_some_and(|f| Utc::now() - f < self.kind.ttl())
+    }
+}
+
+/// Describe every file in the cache, ordered by region and kind.
+pub fn cache_status(root: &Path) -> Vec<CacheEntryStatus> {
+    let mut regions: Vec<String> = fs::read_dir(root).into_iter().flatten()
+        .flatten()
+        .filter(|entry| entry.path().is_dir())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect();
+    regions.sort();
+
+    let mut entries = Vec::new();
+    for region in regions {
+        for kind in CacheKind::ALL {
+            let path = root.join(&region).join(format!("{}.json", kind.name()));
+            if !path.exists() {
+                continue;
+            }
+
+            // The data's shape depends on its kind; any JSON object can be counted.
+            let cached: Option<CachedData<serde_json::Map<String, serde_json::Value>>> = read_file(&path);
+            entries.push(CacheEntryStatus {
+                region: region.clone(),
+                kind,
+                entries: cached.as_ref().map(|c| c.data.len()),
+                fetched_at: cached.map(|c| c.fetched_at),
+            });
+        }
+    }
+
+    entries
+}
+
+/// Remove everything in the cache.  Returns the number of files removed.
+pub fn clear_cache(root: &Path) -> Result<usize, JawsError> {
+    if !root.exists() {
+        return Ok(0);
+    }
+
+    let count = fs::read_dir(root).into_iter().flatten()
+        .flatten()
+        .filter(|entry| entry.path().is_dir())
+        .map(|entry| fs::read_dir(entry.path()).into_iter().flatten().count())
+        .sum();
+
+    fs::remove_dir_all(root).map_err(|e| JawsError::with_kind(JawsErrorKind::Output,
+        format!("Couldn't remove the cache directory '{}': {}", root.display(), e)).with_source(e))?;
+
+    Ok(count)
+}
+
+fn read_file<T: DeserializeOwned>(path: &Path) -> Option<T> {
+    let json = fs::read_to_string(path).ok()?;
+    serde_json::from_str(&json).ok()
+}
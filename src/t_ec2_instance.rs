@@ -1,12 +1,44 @@
 use aws_sdk_ec2::types::Instance;
+use serde::{Deserialize, Serialize};
+
+use crate::matrix_handlers::e_matrix_cell::MatrixCell;
 
 #[derive(Debug)]
 pub struct EC2Instance {
     pub instance: Instance,
-    pub ssm: Option<bool>,
+    pub ssm: Option<Lookup<bool>>,
     pub az: Option<String>,
     pub instance_type: Option<String>,
-    pub spec: Option<String>,
+    pub spec: Option<Lookup<InstanceSpec>>,
+}
+
+/// The outcome of looking up extra information about an instance.  A failed lookup is kept as
+/// `Unknown`, so that the rest of the output can still be produced.
+#[derive(Debug, Clone, Copy)]
+pub enum Lookup<T> {
+    Known(T),
+    Unknown,
+}
+
+impl<T> Lookup<T> {
+    pub fn known(&self) -> Option<&T> {
+        match self {
+            Lookup::Known(value) => Some(value),
+            Lookup::Unknown => None,
+        }
+    }
+
+    /// Render the value as a cell, or the unknown marker.
+    pub fn to_cell(&self, f: impl Fn(&T) -> MatrixCell) -> MatrixCell {
+        self.known().map(f).unwrap_or(MatrixCell::Unknown)
+    }
+}
+
+/// The hardware specification of an instance type.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct InstanceSpec {
+    pub vcpus: i32,
+    pub memory_gib: i64,
 }
 
 impl EC2Instance {
@@ -23,13 +55,19 @@ impl EC2Instance {
         }
     }
 
-    pub fn find_tag_value(&self, key: &str) -> Option<String> {
-        for tag in self.instance.tags.as_ref().unwrap() {
-            if tag.key().unwrap() == key {
-                return Some(tag.value().unwrap().to_string());
-            }
-        }
+    /// All the instance's tags, as (key, value) pairs ordered by key.
+    pub fn tags(&self) -> Vec<(String, String)> {
+        let mut tags: Vec<(String, String)> = self.instance.tags().iter()
+            .filter_map(|tag| Some((tag.key()?.to_string(), tag.value().unwrap_or_default().to_string())))
+            .collect();
+        tags.sort();
+        tags
+    }
 
-        None
+    pub fn find_tag_value(&self, key: &str) -> Option<String> {
+        self.instance.tags().iter()
+            .find(|tag| tag.key() == Some(key))
+            .and_then(|tag| tag.value())
+            .map(|value| value.to_string())
     }
 }
@@ -0,0 +1,206 @@
+use futures::stream::{self, StreamExt};
+
+use crate::commands::res;
+use crate::errors::jaws_error::{JawsError, JawsErrorKind};
+use crate::matrix_handlers::t_matrix_merge::{merge_outputs, LabelledOutput};
+use crate::matrix_handlers::t_matrix_output::{add_note, MatrixOutput};
+use crate::t_accounts::{accounts, Account};
+use crate::t_aws_handler::AWSHandler;
+use crate::{run_command, Options, SubCommands};
+
+/// One run of a command: in an account (or with the current credentials), in a region (or
+/// the default one).  `labels` identify the run in the merged output.
+struct Run {
+    options: Options,
+    labels: Vec<(String, String)>,
+}
+
+/// Run the command in each account given with `--role-arn` or `--accounts-file`, and in each
+/// region given with `--regions` (or every enabled region, with `--all-regions`), at most
+/// `--concurrency` at once.  The outputs are merged, with Account ID and Account columns for
+/// accounts and a Region column for regions.  A run which fails is noted, and the others are
+/// still reported; only if every run fails is the first failure returned.
+///
+/// `res` in several accounts pools their reservations in each region, unless told to keep
+/// the accounts separate.
+pub async fn run_fanned_out(options: &Options) -> Result<Option<MatrixOutput>, JawsError> {
+    if !options.subcommand.is_regional() {
+        return Err(JawsError::with_kind(JawsErrorKind::Usage,
+            "This command can't be run in several regions or accounts.".to_string()));
+    }
+
+    let mut notes: Vec<String> = Vec::new();
+    let mut first_error: Option<JawsError> = None;
+
+    // No accounts means just the current credentials.
+    let accounts: Vec<Option<Account>> = match accounts(options)? {
+        accounts if accounts.is_empty() => vec![None],
+        accounts => accounts.into_iter().map(Some).collect(),
+    };
+
+    let mut runs: Vec<Run> = Vec::new();
+    for account in accounts {
+        let mut account_options = options.clone();
+        account_options.regions = Vec::new();
+        account_options.all_regions = false;
+        account_options.role_arns = Vec::new();
+        account_options.accounts_file = None;
+        account_options.assume_role = account.as_ref().map(|a| a.role_arn.clone());
+        let name = account.as_ref().map(|a| a.id()).unwrap_or("the current account");
+
+        // One handler serves to check the account and to list its regions.  Roles are assumed
+        // lazily, so check that this one can be before going on: if it can't, nothing can be
+        // done in the account.
+        let handler = if account.is_some() || options.all_regions {
+            let handler = match AWSHandler::new(&account_options).await {
+                Ok(handler) if account.is_some() => handler.sts_get_caller_identity().await.map(|_| handler),
+                result => result,
+            };
+            match handler {
+                Ok(handler) => Some(handler),
+                Err(e) => {
+                    note_failure(&mut notes, &mut first_error, name, e);
+                    continue;
+                }
+            }
+        } else {
+            None
+        };
+
+        let mut labels: Vec<(String, String)> = Vec::new();
+        if let (Some(account), Some(handler)) = (&account, &handler) {
+            labels.push(("Account ID".to_string(), account.id().to_string()));
+
+            // Without an alias, the account is named by its ID, so that every row says which
+            // account it's from.
+            let alias = match &account.alias {
+                Some(alias) => Some(alias.clone()),
+                None => handler.iam_get_account_alias().await.unwrap_or_else(|e| {
+                    add_note(&mut notes, format!("{}: account alias unknown: {}", account.id(), e));
+                    None
+                }),
+            };
+            labels.push(("Account".to_string(), alias.unwrap_or_else(|| account.id().to_string())));
+        }
+
+        // Enabled regions can differ between accounts.
+        let regions = match handler.filter(|_| options.all_regions) {
+            Some(handler) => handler.ec2_get_regions().await,
+            None => Ok(options.regions.clone()),
+        };
+
+        match regions {
+            Ok(regions) if regions.is_empty() => runs.push(Run { options: account_options, labels }),
+            Ok(regions) => {
+                for region in regions {
+                    let mut region_options = account_options.clone();
+                    region_options.region = Some(region.clone());
+
+                    let mut region_labels = labels.clone();
+                    region_labels.push(("Region".to_string(), region));
+                    runs.push(Run { options: region_options, labels: region_labels });
+                }
+            }
+            Err(e) => note_failure(&mut notes, &mut first_error, name, e),
+        }
+    }
+
+    let pooled = matches!(options.subcommand, SubCommands::RES { separate_accounts: false, .. })
+        && (!options.role_arns.is_empty() || options.accounts_file.is_some());
+
+    let results: Vec<_> = if pooled {
+        add_note(&mut notes, "Reservations are shared between the accounts, as under consolidated billing.".to_string());
+        run_pooled(options, runs).await
+    } else {
+        stream::iter(runs)
+            .map(|mut run| async move {
+                let result = run_command(&mut run.options).await;
+                (run.labels, result)
+            })
+            .buffered(options.concurrency as usize)
+            .collect()
+            .await
+    };
+
+    let mut outputs: Vec<LabelledOutput> = Vec::new();
+    for (labels, result) in results {
+        match result {
+            Ok(Some(output)) => outputs.push(LabelledOutput { labels, output }),
+            Ok(None) => add_note(&mut notes, format!("{}: nothing to report", describe(&labels))),
+            Err(e) => note_failure(&mut notes, &mut first_error, &describe(&labels), e),
+        }
+    }
+
+    if let (true, Some(e)) = (outputs.is_empty(), first_error) {
+        return Err(e);
+    }
+
+    let mut merged = merge_outputs(outputs);
+    if let Some(matrix) = merged.as_mut().and_then(|m| m.matrices.first_mut()) {
+        for note in notes {
+            add_note(matrix.notes.get_or_insert_with(Vec::new), note);
+        }
+    }
+
+    Ok(merged)
+}
+
+/// Run `res` in every account at once in each region, so that coverage is worked out over
+/// all their reservations.  Regions are run concurrently.  Results come back in the order of
+/// the runs.
+async fn run_pooled(options: &Options, runs: Vec<Run>)
+                    -> Vec<(Vec<(String, String)>, Result<Option<MatrixOutput>, JawsError>)> {
+    // Each region's runs, numbered in the order given.
+    let mut regions: Vec<Vec<(usize, Run)>> = Vec::new();
+    for (n, run) in runs.into_iter().enumerate() {
+        match regions.iter_mut().find(|region_runs| region_runs[0].1.options.region == run.options.region) {
+            Some(region_runs) => region_runs.push((n, run)),
+            None => regions.push(vec![(n, run)]),
+        }
+    }
+
+    let mut results = stream::iter(regions)
+        .map(|region_runs| async move {
+            let mut results = Vec::new();
+            let mut pooled = Vec::new();
+            let mut accounts = Vec::new();
+            for (n, run) in region_runs {
+                match AWSHandler::new(&run.options).await {
+                    Ok(handler) => {
+                        pooled.push((n, run.labels));
+                        accounts.push((run.options, handler));
+                    }
+                    Err(e) => results.push((n, (run.labels, Err(e)))),
+                }
+            }
+
+            let pooled_results = res::run_pooled(options, accounts).await;
+            results.extend(pooled.into_iter().zip(pooled_results).map(|((n, labels), result)| (n, (labels, result))));
+            results
+        })
+        .buffered(options.concurrency as usize)
+        .flat_map(stream::iter)
+        .collect::<Vec<_>>()
+        .await;
+
+    results.sort_by_key(|(n, _)| *n);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Note that something failed, keeping the first failure, which is returned if nothing
+/// succeeds.
+fn note_failure(notes: &mut Vec<String>, first_error: &mut Option<JawsError>, what: &str, error: JawsError) {
+    add_note(notes, format!("{}: {}", what, error));
+    first_error.get_or_insert(error);
+}
+
+/// A run's labels, for notes, e.g. "111122223333 / prod / eu-west-1".  An account without
+/// an alias is named only once.
+fn describe(labels: &[(String, String)]) -> String {
+    let mut values: Vec<&str> = labels.iter()
+        .map(|(_, value)| value.as_str())
+        .filter(|value| !value.is_empty())
+        .collect();
+    values.dedup();
+    values.join(" / ")
+}
@@ -0,0 +1,177 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use aws_sdk_ec2::types::Filter;
+
+/// The instance states other than terminated, for `--hide-terminated`.
+const LIVE_STATES: [&str; 5] = ["pending", "running", "shutting-down", "stopping", "stopped"];
+
+/// Which instances the `ec2` command lists.  These are sent to AWS as `DescribeInstances`
+/// filters, so only matching instances are downloaded.  Each option may be repeated (or take
+/// a comma-separated list) to match any of several values; instances must match every option
+/// given.  Values may contain the wildcards `*` and `?`.
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct InstanceFilters {
+    /// Only instances in this state: pending, running, shutting-down, stopping, stopped or
+    /// terminated.
+    #[clap(long = "state", value_name = "STATE", value_delimiter = ',')]
+    pub states: Vec<String>,
+
+    /// Only instances with this tag, as KEY=VALUE, or KEY for any value.  Instances must
+    /// have every tag named, with any of the values given for it.
+    #[clap(long = "tag", value_name = "KEY[=VALUE]")]
+    pub tags: Vec<TagFilter>,
+
+    /// Only instances whose Name tag matches, e.g. "web-*".
+    #[clap(long = "name", value_name = "GLOB")]
+    pub names: Vec<String>,
+
+    /// Only instances of this type, e.g. "m5.large" or "t3.*".
+    #[clap(long = "type", value_name = "TYPE", value_delimiter = ',')]
+    pub types: Vec<String>,
+
+    /// Only instances in this availability zone.
+    #[clap(long = "az", value_name = "AZ", value_delimiter = ',')]
+    pub azs: Vec<String>,
+
+    /// Only instances in this VPC.
+    #[clap(long = "vpc", value_name = "VPC_ID", value_delimiter = ',')]
+    pub vpcs: Vec<String>,
+
+    /// Only the instance with this ID.
+    #[clap(long = "id", value_name = "INSTANCE_ID", value_delimiter = ',')]
+    pub ids: Vec<String>,
+
+    /// Leave out terminated instances.  Set from the `ec2` listing's `--hide-terminated`; the
+    /// lifecycle actions never match terminated instances anyway.
+    #[clap(skip)]
+    pub hide_terminated: bool,
+}
+
+impl InstanceFilters {
+    /// Only running instances.
+    pub fn running() -> Self {
+        Self { states: vec!["running".to_string()], ..Default::default() }
+    }
+
+    /// The filters in the form `DescribeInstances` takes them.
+    pub fn to_sdk(&self) -> Vec<Filter> {
+        let mut filters: Vec<Filter> = Vec::new();
+        let mut add = |name: &str, values: &[String]| {
+            if !values.is_empty() {
+                filters.push(Filter::builder().name(name).set_values(Some(values.to_vec())).build());
+            }
+        };
+
+        if self.hide_terminated {
+            add("instance-state-name", &LIVE_STATES.map(String::from));
+        }
+        add("instance-state-name", &self.states);
+        add("tag:Name", &self.names);
+        add("instance-type", &self.types);
+        add("availability-zone", &self.azs);
+        add("vpc-id", &self.vpcs);
+        add("instance-id", &self.ids);
+
+        // Values for the same tag are alternatives; different tags must all match.
+        let mut tag_values: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+        for tag in self.tags.iter().filter(|tag| tag.value.is_some()) {
+            tag_values.entry(&tag.key).or_default().extend(tag.value.clone());
+        }
+        for (key, values) in &tag_values {
+            add(&format!("tag:{}", key), values);
+        }
+        for tag in self.tags.iter().filter(|tag| tag.value.is_none()) {
+            add("tag-key", std::slice::from_ref(&tag.key));
+        }
+
+        filters
+    }
+}
+
+/// A `--tag` filter: a tag key, and optionally the value it must have.
+#[derive(Clone, Debug)]
+pub struct TagFilter {
+    pub key: String,
+    pub value: Option<String>,
+}
+
+/// Parses `KEY=VALUE` or `KEY`.
+impl FromStr for TagFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, value) = match s.split_once('=') {
+            Some((key, value)) => (key, Some(value.to_string())),
+            None => (s, None),
+        };
+
+        if key.is_empty() {
+            return Err(format!("'{}' isn't a tag filter; use KEY=VALUE or KEY", s));
+        }
+        Ok(TagFilter { key: key.to_string(), value })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The filters as (name, values) pairs.
+    fn sdk_filters(filters: &InstanceFilters) -> Vec<(String, Vec<String>)> {
+        filters.to_sdk().iter()
+            .map(|f| (f.name().unwrap().to_string(), f.values().to_vec()))
+            .collect()
+    }
+
+    fn filter(name: &str, values: &[&str]) -> (String, Vec<String>) {
+        (name.to_string(), values.iter().map(|v| v.to_string()).collect())
+    }
+
+    #[test]
+    fn parses_tag_filters() {
+        let tag: TagFilter = "env=prod=1".parse().unwrap();
+        assert_eq!((tag.key.as_str(), tag.value.as_deref()), ("env", Some("prod=1")));
+
+        let tag: TagFilter = "Owner".parse().unwrap();
+        assert_eq!((tag.key.as_str(), tag.value), ("Owner", None));
+
+        let tag: TagFilter = "env=".parse().unwrap();
+        assert_eq!(tag.value.as_deref(), Some(""));
+
+        assert!("=prod".parse::<TagFilter>().unwrap_err().contains("isn't a tag filter"));
+    }
+
+    #[test]
+    fn no_filters_match_everything() {
+        assert!(InstanceFilters::default().to_sdk().is_empty());
+    }
+
+    #[test]
+    fn converts_each_option_to_a_filter() {
+        let filters = InstanceFilters {
+            states: vec!["running".to_string(), "stopped".to_string()],
+            names: vec!["web-*".to_string()],
+            types: vec!["m5.*".to_string()],
+            ids: vec!["i-01".to_string()],
+            tags: ["env=prod", "team=web", "env=staging", "Owner"].iter().map(|t| t.parse().unwrap()).collect(),
+            ..Default::default()
+        };
+
+        assert_eq!(sdk_filters(&filters), [
+            filter("instance-state-name", &["running", "stopped"]),
+            filter("tag:Name", &["web-*"]),
+            filter("instance-type", &["m5.*"]),
+            filter("instance-id", &["i-01"]),
+            filter("tag:env", &["prod", "staging"]),
+            filter("tag:team", &["web"]),
+            filter("tag-key", &["Owner"]),
+        ]);
+    }
+
+    #[test]
+    fn hide_terminated_asks_for_live_states() {
+        let filters = InstanceFilters { hide_terminated: true, ..Default::default() };
+        assert_eq!(sdk_filters(&filters), [filter("instance-state-name", &LIVE_STATES)]);
+    }
+}
@@ -0,0 +1,37 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::e_output_format::OutputFormat;
+
+/// One destination for a command's matrix output: a format, and the file it should be
+/// written to.  If there is no file, the output goes to stdout.
+#[derive(Clone, Debug)]
+pub struct OutputTarget {
+    pub format: OutputFormat,
+    pub file: Option<PathBuf>,
+}
+
+impl OutputTarget {
+    /// True if this target writes to the terminal (stdout) rather than to a file.
+    pub fn is_stdout(&self) -> bool {
+        self.file.is_none()
+    }
+}
+
+/// Parses `FORMAT` or `FORMAT:FILE`, e.g. `json:report.json`.
+impl FromStr for OutputTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (format, file) = match s.split_once(':') {
+            Some((format, file)) if !file.is_empty() => (format, Some(PathBuf::from(file))),
+            Some((format, _)) => (format, None),
+            None => (s, None),
+        };
+
+        Ok(OutputTarget {
+            format: OutputFormat::from_str(format)?,
+            file,
+        })
+    }
+}
@@ -1,4 +1,4 @@
-use std::io::{stdout, Write};
+use std::io::{stderr, stdin, stdout, Write};
 
 use terminal_size::{Height as TerminalHeight, Height, terminal_size, Width as TerminalWidth, Width};
 use termion::clear::CurrentLine;
@@ -16,8 +16,12 @@ pub struct Textutil {
 
 impl Textutil {
     pub(crate) fn new(options: &Options) -> Textutil {
+        // Progress messages go to the terminal, so they must be suppressed if anything that
+        // isn't free text (e.g. JSON) is being written there too.
         Self {
-            mute: !options.output_format.unwrap().supports_free_text_output()
+            mute: !options.output_targets().iter()
+                .filter(|t| t.is_stdout())
+                .all(|t| t.format.supports_free_text_output())
         }
     }
 
@@ -69,6 +73,17 @@ impl Textutil {
     }
 }
 
+/// Ask a yes/no question on the terminal.  Anything but "y" or "yes" is a no.  The question
+/// goes to stderr, so that it never ends up in redirected output.
+pub fn confirm(question: &str) -> std::io::Result<bool> {
+    eprint!("{} [y/N] ", question);
+    stderr().flush()?;
+
+    let mut answer = String::new();
+    stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
 pub fn get_terminal_size() -> (usize, usize) {
     // We need to do unwrap_or here and supply a default, because if Jaws is run inside a unix
     // pipeline, there is no tty.